@@ -0,0 +1,36 @@
+/// Commands of the `commands` feature, which get `allow-*` and `deny-*`
+/// permissions under `permissions/autogenerated`.
+const COMMANDS: &[&str] = &[
+    "set_idle_threshold",
+    "get_idle_threshold",
+    "get_active_backend",
+    "set_active_backend",
+    "get_capabilities",
+    "get_full_state",
+    "get_session_info",
+    "await_ready",
+    "get_supported_events",
+    "get_stats",
+    "get_lock_metrics",
+    "get_lock_status",
+];
+
+fn main() {
+    // Sets `linux_backend`, `macos_backend` and `windows_backend` when
+    // building for that OS with its `backend-*` feature, unless `no-op`
+    // turns all of them off. The code checks these instead of `target_os`.
+    let target_os = std::env::var("CARGO_CFG_TARGET_OS").unwrap_or_default();
+    let enabled = |feature: &str| std::env::var_os(format!("CARGO_FEATURE_{}", feature)).is_some();
+    for (os, feature, cfg) in [
+        ("linux", "BACKEND_LINUX", "linux_backend"),
+        ("macos", "BACKEND_MACOS", "macos_backend"),
+        ("windows", "BACKEND_WINDOWS", "windows_backend"),
+    ] {
+        println!("cargo:rustc-check-cfg=cfg({})", cfg);
+        if target_os == os && enabled(feature) && !enabled("NO_OP") {
+            println!("cargo:rustc-cfg={}", cfg);
+        }
+    }
+
+    tauri_plugin::Builder::new(COMMANDS).build();
+}
@@ -0,0 +1,53 @@
+import { invoke } from '@tauri-apps/api/core'
+import { listen, type UnlistenFn } from '@tauri-apps/api/event'
+
+/** Channel of the lock and unlock events, unless moved with `Builder::event_name`. */
+export const CHANGE_SESSION_STATUS = 'window_screen_lock_status://change_session_status'
+
+/**
+ * Lock state of the session. `screensaver_active` and `display_sleep` refine
+ * `unlocked` and are only reported with `Builder::detailed_status`.
+ */
+export type LockStatus = 'locked' | 'unlocked' | 'screensaver_active' | 'display_sleep'
+
+/** Best-effort hint about what caused a lock, macOS only. */
+export type LockReason = 'remote' | 'managed'
+
+/** Payload of the lock and unlock events. */
+export interface SessionStatusEvent {
+  status: LockStatus
+  reason?: LockReason
+  /** Only set on locks with `Builder::annotate_requires_password`. */
+  requires_password?: boolean
+  /** Unix time in ms at which the backend detected the transition. */
+  timestamp_ms: number
+  /** Increases by one with every event the plugin emits, so a gap means one was missed. */
+  seq: number
+}
+
+export interface LockStatusOptions {
+  /** The name set with `Builder::event_name`, if any. */
+  eventName?: string
+}
+
+/**
+ * Calls `callback` with every lock status change, and the full event. The
+ * returned function stops listening.
+ */
+export async function onLockStatusChange(
+  callback: (status: LockStatus, event: SessionStatusEvent) => void,
+  options: LockStatusOptions = {}
+): Promise<UnlistenFn> {
+  return await listen<SessionStatusEvent>(options.eventName ?? CHANGE_SESSION_STATUS, (event) => {
+    callback(event.payload.status, event.payload)
+  })
+}
+
+/**
+ * Reads the lock status of the session right now from the OS, see the
+ * `get_lock_status` command. Rejects with `{ kind, message? }` if it can't be
+ * read, e.g. with `not_ready` on Windows before the first change.
+ */
+export async function getLockStatus(): Promise<LockStatus> {
+  return await invoke<LockStatus>('plugin:window-screen-lock-status|get_lock_status')
+}
@@ -0,0 +1,194 @@
+use crate::dispatch;
+use crate::error::{Result, ScreenLockError};
+use crate::models::{
+    BackendKind, Capabilities, FullState, LockMetrics, LockStatus, SessionInfo, Stats,
+};
+use crate::state::SharedState;
+use std::time::Duration;
+use tauri::{AppHandle, Manager, Runtime, State};
+
+#[tauri::command]
+pub(crate) fn set_idle_threshold(state: State<'_, SharedState>, ms: u64) -> Result<()> {
+    state.set_idle_threshold(ms)
+}
+
+#[tauri::command]
+pub(crate) fn get_idle_threshold(state: State<'_, SharedState>) -> Result<u64> {
+    Ok(state.idle_threshold().as_millis() as u64)
+}
+
+#[tauri::command]
+pub(crate) fn get_active_backend(state: State<'_, SharedState>) -> Result<Option<BackendKind>> {
+    Ok(state.active_backend())
+}
+
+/// Replaces the running backend with `backend`, which must be one of the
+/// available backends. Only Linux has more than one.
+#[tauri::command]
+pub(crate) fn set_active_backend<R: Runtime>(
+    app: AppHandle<R>,
+    backend: BackendKind,
+) -> Result<()> {
+    let state = app.state::<SharedState>();
+    if !state.available_backends().contains(&backend) {
+        return Err(ScreenLockError::BackendUnavailable);
+    }
+    if state.active_backend() == Some(backend) {
+        return Ok(());
+    }
+    #[cfg(linux_backend)]
+    return crate::linux::switch_backend(&app, backend);
+    #[cfg(not(linux_backend))]
+    Err(ScreenLockError::NotReady)
+}
+
+#[tauri::command]
+pub(crate) fn get_capabilities(state: State<'_, SharedState>) -> Result<Capabilities> {
+    let active_backend = state.active_backend();
+    // `simulate` can report every detailed status.
+    let mock = cfg!(feature = "mock") && active_backend.is_some();
+    Ok(Capabilities {
+        lock: active_backend.is_some(),
+        lock_reason: cfg!(macos_backend),
+        screensaver: is_screensaver(active_backend)
+            || (cfg!(macos_backend) && active_backend.is_some())
+            || mock,
+        idle: state.presence_events()
+            && cfg!(any(linux_backend, macos_backend, windows_backend))
+            && !cfg!(feature = "mock"),
+        display_power: (cfg!(windows_backend) && active_backend.is_some()) || mock,
+        lid: false,
+        is_remote: cfg!(macos_backend),
+        available_backends: state.available_backends(),
+        active_backend,
+    })
+}
+
+#[tauri::command]
+pub(crate) fn get_full_state(state: State<'_, SharedState>) -> Result<FullState> {
+    Ok(state.full_state())
+}
+
+fn is_screensaver(backend: Option<BackendKind>) -> bool {
+    matches!(
+        backend,
+        Some(
+            BackendKind::GnomeScreenSaver
+                | BackendKind::KdeScreenSaver
+                | BackendKind::FreedesktopScreenSaver
+        )
+    )
+}
+
+/// Detection latency, only recorded with the `latency-metrics` feature.
+#[tauri::command]
+pub(crate) fn get_stats(state: State<'_, SharedState>) -> Result<Stats> {
+    #[cfg(feature = "latency-metrics")]
+    return Ok(state.stats());
+    #[cfg(not(feature = "latency-metrics"))]
+    {
+        let _ = state;
+        Err(ScreenLockError::Unsupported)
+    }
+}
+
+#[tauri::command]
+pub(crate) fn get_lock_metrics(state: State<'_, SharedState>) -> Result<LockMetrics> {
+    Ok(dispatch::metrics(&state))
+}
+
+/// Like [`crate::supported_events`], with the lock and unlock channel set
+/// with `Builder::event_name`.
+#[tauri::command]
+pub(crate) fn get_supported_events(state: State<'_, SharedState>) -> Result<Vec<String>> {
+    Ok(crate::supported_events()
+        .iter()
+        .map(|event| match *event {
+            crate::events::CHANGE_SESSION_STATUS => state.event_name().to_string(),
+            event => event.to_string(),
+        })
+        .collect())
+}
+
+/// Reads the session settings off the async runtime, since on Linux that
+/// runs `gsettings`.
+#[tauri::command]
+pub(crate) async fn get_session_info() -> Result<SessionInfo> {
+    tauri::async_runtime::spawn_blocking(|| SessionInfo {
+        requires_password: crate::requires_password(),
+    })
+    .await
+    .map_err(|e| ScreenLockError::PlatformError(e.to_string()))
+}
+
+/// Reads the lock state from the OS rather than the last event: the
+/// running backend on Linux, logind's `LockedHint` or the screensaver's
+/// `GetActive` (`LockedHint` for GNOME's in logind mode), and the session
+/// dictionary on macOS. Windows has no reliable
+/// query, so the state last seen by the monitor is returned there, as it is
+/// with the `mock` feature. An unlocked session is refined as in the events
+/// with `Builder::detailed_status`.
+#[tauri::command]
+pub(crate) async fn get_lock_status<R: Runtime>(app: AppHandle<R>) -> Result<LockStatus> {
+    tauri::async_runtime::spawn_blocking(move || {
+        query_locked(&app).map(|locked| app.state::<SharedState>().status(locked))
+    })
+    .await
+    .map_err(|e| ScreenLockError::PlatformError(e.to_string()))?
+}
+
+#[cfg_attr(macos_backend, allow(unused_variables))]
+fn query_locked<R: Runtime>(app: &AppHandle<R>) -> Result<bool> {
+    // The mock backend has no OS state to read.
+    if cfg!(feature = "mock") {
+        return app
+            .state::<SharedState>()
+            .session()
+            .locked
+            .ok_or(ScreenLockError::NotReady);
+    }
+    #[cfg(linux_backend)]
+    let locked = crate::linux::query_locked(app);
+    #[cfg(macos_backend)]
+    let locked = crate::macos::query_locked();
+    #[cfg(windows_backend)]
+    let locked = app
+        .state::<SharedState>()
+        .session()
+        .locked
+        .ok_or(ScreenLockError::NotReady);
+    #[cfg(not(any(linux_backend, macos_backend, windows_backend)))]
+    let locked = {
+        let _ = app;
+        Err(ScreenLockError::Unsupported)
+    };
+    locked
+}
+
+/// Resolves once the monitor has subscribed to a backend, waiting at most
+/// `timeout_ms` if given.
+#[tauri::command]
+pub(crate) async fn await_ready<R: Runtime>(
+    app: AppHandle<R>,
+    timeout_ms: Option<u64>,
+) -> Result<()> {
+    if !cfg!(any(
+        linux_backend,
+        macos_backend,
+        windows_backend,
+        feature = "mock"
+    )) {
+        return Err(ScreenLockError::Unsupported);
+    }
+    let ready = tauri::async_runtime::spawn_blocking(move || {
+        app.state::<SharedState>()
+            .wait_ready(timeout_ms.map(Duration::from_millis))
+    })
+    .await
+    .map_err(|e| ScreenLockError::PlatformError(e.to_string()))?;
+    match ready {
+        Some(true) => Ok(()),
+        Some(false) => Err(ScreenLockError::BackendUnavailable),
+        None => Err(ScreenLockError::NotReady),
+    }
+}
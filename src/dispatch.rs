@@ -0,0 +1,592 @@
+use crate::error::ScreenLockError;
+use crate::events;
+use crate::logging::{info, warn};
+#[cfg(feature = "commands")]
+use crate::models::LockMetrics;
+#[cfg(any(macos_backend, windows_backend))]
+use crate::models::PreLockEvent;
+#[cfg(any(linux_backend, macos_backend, windows_backend))]
+use crate::models::PresenceEvent;
+#[cfg(linux_backend)]
+use crate::models::ScreensaverEvent;
+use crate::models::{
+    AnyChangeEvent, BackendErrorEvent, BackendKind, LockReason, LockStatus, MonitorErrorEvent,
+    MonitoringStartedEvent, PersistedState, RestoredEvent, SessionStatusEvent,
+};
+#[cfg(any(linux_backend, windows_backend))]
+use crate::models::{RawEvent, RawValue};
+use crate::state::{now_ms, SharedState};
+use crate::supervisor;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::MutexGuard;
+use std::thread;
+#[cfg(any(macos_backend, windows_backend))]
+use std::time::Duration;
+use std::time::Instant;
+use tauri::{AppHandle, Emitter, EventTarget, Manager, Runtime};
+
+pub(crate) struct Delivery {
+    /// Bumped on every detected change so a pending debounced unlock can tell
+    /// whether it has been superseded.
+    generation: u64,
+    /// Last status sent to the frontend.
+    delivered: Option<LockStatus>,
+    /// Most recent delivered events, replayed to newly loaded windows.
+    history: VecDeque<SessionStatusEvent>,
+    /// Delivered transitions, kept with `delivered` so the counts always
+    /// match the events sent.
+    lock_count: u64,
+    unlock_count: u64,
+    last_change_ms: Option<u64>,
+    /// Delivered events whose hooks, emit and publish haven't run yet. They
+    /// run with the lock released, one thread at a time and in order.
+    pending: VecDeque<Notification>,
+    /// Set while a thread runs the pending notifications.
+    notifying: bool,
+}
+
+/// What remains to be done for a delivered event once the delivery lock is
+/// released, so hooks and listeners may call back into the plugin.
+struct Notification {
+    status: LockStatus,
+    event: SessionStatusEvent,
+    persisted: Option<PersistedState>,
+    detected_at: Instant,
+}
+
+impl Delivery {
+    pub(crate) const fn new() -> Self {
+        Self {
+            generation: 0,
+            delivered: None,
+            history: VecDeque::new(),
+            lock_count: 0,
+            unlock_count: 0,
+            last_change_ms: None,
+            pending: VecDeque::new(),
+            notifying: false,
+        }
+    }
+}
+
+/// Handles a lock state change detected by a backend.
+///
+/// Locks are delivered right away. Unlocks are held back for the configured
+/// unlock debounce and dropped if the session locks again in the meantime.
+/// `detected_at` is the earliest moment the backend could have seen the
+/// change, used for the latency metrics.
+///
+/// Repeats of the last known state are ignored, whichever backend reports
+/// them, and don't restart a pending unlock debounce. Returns whether
+/// `locked` was a change.
+pub(crate) fn status_changed<R: Runtime>(
+    app: &AppHandle<R>,
+    locked: bool,
+    reason: Option<LockReason>,
+    detected_at: Instant,
+) -> bool {
+    let state = app.state::<SharedState>();
+    // Backends do report repeats: Windows may post several session change
+    // messages for one lock, and logind also signals unrelated properties.
+    if !state.record_lock(locked) {
+        return false;
+    }
+    // Presence follows the lock state without waiting for the next poll.
+    state.wake();
+
+    let requires_password = if locked && state.annotate_requires_password() {
+        crate::requires_password()
+    } else {
+        None
+    };
+
+    let mut delivery = state.delivery();
+    delivery.generation += 1;
+    match state.unlock_debounce() {
+        Some(debounce) if !locked => {
+            let generation = delivery.generation;
+            let handle = app.clone();
+            let spawned = supervisor::spawn_named(app, "screen-lock-debounce", false, move || {
+                thread::sleep(debounce);
+                let state = handle.state::<SharedState>();
+                let delivery = state.delivery();
+                if delivery.generation == generation && !state.is_stopped() {
+                    deliver(&handle, &state, delivery, false, None, None, detected_at);
+                }
+            });
+            // Delivered right away rather than lost.
+            if !spawned {
+                deliver(app, &state, delivery, false, None, None, detected_at);
+            }
+        }
+        _ => deliver(
+            app,
+            &state,
+            delivery,
+            locked,
+            reason,
+            requires_password,
+            detected_at,
+        ),
+    }
+    true
+}
+
+/// Passes a lock state read by `backend` to the `on_raw_read` hook, before
+/// any debouncing or deduplication.
+///
+/// The first read after a restore also settles the restored state: if it
+/// differs, `restored` is emitted and the live state delivered.
+pub(crate) fn raw_read<R: Runtime>(
+    app: &AppHandle<R>,
+    backend: BackendKind,
+    locked: bool,
+    detected_at: Instant,
+) {
+    let state = app.state::<SharedState>();
+    if let Some(hook) = state.on_raw_read() {
+        hook(backend, LockStatus::from(locked), detected_at.elapsed());
+    }
+    match state.take_restored() {
+        Some(persisted) if persisted != locked => {
+            emit(
+                app,
+                events::RESTORED,
+                RestoredEvent {
+                    persisted,
+                    locked,
+                    seq: state.next_seq(),
+                },
+            );
+            status_changed(app, locked, None, detected_at);
+        }
+        _ => {}
+    }
+}
+
+/// Reports the lock state `backend` read when monitoring started. It is
+/// delivered like a change, unless `Builder::initial_status` is off: then it
+/// is only recorded, so the first event is a transition away from it.
+/// Nothing is delivered if a state was restored or already delivered.
+#[cfg_attr(not(any(linux_backend, macos_backend)), allow(dead_code))]
+pub(crate) fn initial_status<R: Runtime>(
+    app: &AppHandle<R>,
+    backend: BackendKind,
+    locked: bool,
+    reason: Option<LockReason>,
+    detected_at: Instant,
+) {
+    raw_read(app, backend, locked, detected_at);
+    let state = app.state::<SharedState>();
+    state.record_initial_lock(locked);
+    state.wake();
+    let mut delivery = state.delivery();
+    if delivery.delivered.is_some() {
+        return;
+    }
+    if !state.initial_status() {
+        delivery.delivered = Some(state.status(locked));
+        return;
+    }
+    let requires_password = if locked && state.annotate_requires_password() {
+        crate::requires_password()
+    } else {
+        None
+    };
+    deliver(
+        app,
+        &state,
+        delivery,
+        locked,
+        reason,
+        requires_password,
+        detected_at,
+    );
+}
+
+/// Seeds `state` with the lock state and replay history of a previous run.
+/// The replayed events get fresh sequence numbers.
+pub(crate) fn restore(state: &SharedState, persisted: PersistedState) {
+    let mut delivery = state.delivery();
+    if let Some(locked) = persisted.locked {
+        state.record_restored_lock(locked, persisted.since_ms);
+        delivery.delivered = Some(LockStatus::from(locked));
+    }
+    let timestamp_ms = persisted.since_ms.unwrap_or_else(now_ms);
+    let skip = persisted
+        .history
+        .len()
+        .saturating_sub(state.replay_on_window_create());
+    delivery.history = persisted
+        .history
+        .into_iter()
+        .skip(skip)
+        .map(|locked| {
+            if locked {
+                SessionStatusEvent::lock(state.next_seq(), None, timestamp_ms)
+            } else {
+                SessionStatusEvent::unlock(state.next_seq(), timestamp_ms)
+            }
+        })
+        .collect();
+}
+
+/// Forwards an unprocessed backend signal when raw events are enabled.
+#[cfg(any(linux_backend, windows_backend))]
+pub(crate) fn raw_event<R: Runtime>(app: &AppHandle<R>, backend: BackendKind, value: RawValue) {
+    let state = app.state::<SharedState>();
+    if !state.raw_events() {
+        return;
+    }
+    emit(
+        app,
+        events::RAW_EVENT,
+        RawEvent {
+            backend,
+            value,
+            timestamp_ms: now_ms(),
+            seq: state.next_seq(),
+        },
+    );
+}
+
+/// Reports the screensaver turning on or off when that channel is enabled.
+#[cfg(linux_backend)]
+pub(crate) fn screensaver_changed<R: Runtime>(app: &AppHandle<R>, active: bool) {
+    let state = app.state::<SharedState>();
+    if !state.screensaver_events() {
+        return;
+    }
+    emit(
+        app,
+        events::SCREENSAVER_CHANGED,
+        ScreensaverEvent {
+            active,
+            locked: state.session().locked,
+            seq: state.next_seq(),
+        },
+    );
+}
+
+/// Warns the frontend of a lock expected in `lock_in`, unless the session is
+/// already locked.
+#[cfg(any(macos_backend, windows_backend))]
+pub(crate) fn pre_lock<R: Runtime>(app: &AppHandle<R>, lock_in: Option<Duration>) {
+    let state = app.state::<SharedState>();
+    if state.session().locked == Some(true) {
+        return;
+    }
+    emit(
+        app,
+        events::PRE_LOCK,
+        PreLockEvent {
+            lock_in_ms: lock_in.map(|lock_in| lock_in.as_millis() as u64),
+            seq: state.next_seq(),
+        },
+    );
+}
+
+/// Reports a change of presence, see [`SharedState::record_idle`].
+#[cfg(any(linux_backend, macos_backend, windows_backend))]
+pub(crate) fn presence_changed<R: Runtime>(app: &AppHandle<R>, present: bool) {
+    let state = app.state::<SharedState>();
+    emit(
+        app,
+        events::PRESENCE_CHANGED,
+        PresenceEvent {
+            present,
+            idle_ms: state.session().idle_ms,
+            seq: state.next_seq(),
+        },
+    );
+    any_change(app);
+}
+
+/// Handles a screensaver or display change of an unlocked session. With
+/// detailed statuses, the refined status is delivered if it changed;
+/// otherwise only `any_change` is emitted.
+#[cfg(any(linux_backend, macos_backend, windows_backend, feature = "mock"))]
+pub(crate) fn detail_changed<R: Runtime>(app: &AppHandle<R>, detected_at: Instant) {
+    let state = app.state::<SharedState>();
+    let delivery = state.delivery();
+    // Refined statuses only replace `Unlocked`. A pending debounced unlock
+    // picks up the new details once it is delivered.
+    let unlocked =
+        state.session().locked != Some(true) && delivery.delivered != Some(LockStatus::Locked);
+    if !state.detailed_status() || !unlocked || delivery.delivered == Some(state.status(false)) {
+        drop(delivery);
+        any_change(app);
+        return;
+    }
+    deliver(app, &state, delivery, false, None, None, detected_at);
+}
+
+/// Emits the current session state when any-change events are enabled.
+pub(crate) fn any_change<R: Runtime>(app: &AppHandle<R>) {
+    let state = app.state::<SharedState>();
+    if !state.any_change_events() {
+        return;
+    }
+    emit(
+        app,
+        events::ANY_CHANGE,
+        AnyChangeEvent {
+            state: state.full_state(),
+            seq: state.next_seq(),
+        },
+    );
+}
+
+/// Marks the monitor as ready and announces it to the frontend.
+pub(crate) fn monitoring_started<R: Runtime>(app: &AppHandle<R>, backend: BackendKind) {
+    let state = app.state::<SharedState>();
+    state.set_ready(true);
+    emit(
+        app,
+        events::MONITORING_STARTED,
+        MonitoringStartedEvent {
+            backend,
+            seq: state.next_seq(),
+        },
+    );
+}
+
+/// Reports a panicked monitoring thread to the frontend.
+pub(crate) fn monitor_error<R: Runtime>(
+    app: &AppHandle<R>,
+    message: String,
+    restarts: u32,
+    will_restart: bool,
+) {
+    emit(
+        app,
+        events::MONITOR_ERROR,
+        MonitorErrorEvent {
+            message,
+            restarts,
+            will_restart,
+            seq: app.state::<SharedState>().next_seq(),
+        },
+    );
+}
+
+/// Logs a backend failure and reports it to the frontend. `fatal` means lock
+/// detection has stopped and won't resume by itself.
+pub(crate) fn backend_error<R: Runtime>(
+    app: &AppHandle<R>,
+    backend: Option<BackendKind>,
+    error: ScreenLockError,
+    fatal: bool,
+) {
+    warn!("{}", error);
+    emit(
+        app,
+        events::BACKEND_ERROR,
+        BackendErrorEvent {
+            error,
+            backend,
+            fatal,
+            seq: app.state::<SharedState>().next_seq(),
+        },
+    );
+}
+
+/// Counts of the delivered transitions, for `get_lock_metrics`.
+#[cfg(feature = "commands")]
+pub(crate) fn metrics(state: &SharedState) -> LockMetrics {
+    let delivery = state.delivery();
+    LockMetrics {
+        lock_count: delivery.lock_count,
+        unlock_count: delivery.unlock_count,
+        last_change_ms: delivery.last_change_ms,
+        current: delivery.delivered,
+    }
+}
+
+/// Status last sent to the frontend.
+#[cfg(feature = "async")]
+pub(crate) fn delivered(state: &SharedState) -> Option<LockStatus> {
+    state.delivery().delivered
+}
+
+/// Events to replay to a newly loaded window, oldest first.
+pub(crate) fn replay_events(state: &SharedState) -> Vec<SessionStatusEvent> {
+    state.delivery().history.iter().cloned().collect()
+}
+
+/// Broadcasts `payload`, or with `Builder::emit_to` only sends it to that
+/// window and with `Builder::emit_filter` to the windows and webviews the
+/// filter accepts. Rust listeners on the app are not windows and always
+/// receive it.
+fn emit<R: Runtime, S: Serialize + Clone>(app: &AppHandle<R>, event: &str, payload: S) {
+    let state = app.state::<SharedState>();
+    if let Some(label) = state.emit_target() {
+        if app.get_webview_window(label).is_none() {
+            warn!(
+                "No window labelled {:?}, {} is only sent to app listeners",
+                label, event
+            );
+        }
+        let _ = app.emit_to(label, event, payload);
+        return;
+    }
+    let _ = match state.emit_filter() {
+        Some(_) => app.emit_filter(event, payload, |target| match target {
+            EventTarget::AnyLabel { label }
+            | EventTarget::Window { label }
+            | EventTarget::Webview { label }
+            | EventTarget::WebviewWindow { label } => state.emits_to(label),
+            _ => true,
+        }),
+        None => app.emit(event, payload),
+    };
+}
+
+/// Records `status` as delivered and queues its event, then releases
+/// `delivery` to run the hooks, emit and publish. If another thread is
+/// already running them, e.g. a hook that reported a change itself, that one
+/// picks the event up after its current one, so they stay in order.
+fn deliver<'a, R: Runtime>(
+    app: &AppHandle<R>,
+    state: &'a SharedState,
+    mut delivery: MutexGuard<'a, Delivery>,
+    locked: bool,
+    reason: Option<LockReason>,
+    requires_password: Option<bool>,
+    detected_at: Instant,
+) {
+    // A lock that cancelled a pending unlock leaves the frontend state as is.
+    let status = state.status(locked);
+    if delivery.delivered == Some(status) {
+        return;
+    }
+    let previous = delivery.delivered.replace(status);
+
+    // Wall-clock time of the detection, which may lie in the past for a
+    // debounced unlock.
+    let timestamp_ms = now_ms().saturating_sub(detected_at.elapsed().as_millis() as u64);
+    // The initial state and detailed unlocked statuses aren't transitions.
+    if let Some(previous) = previous {
+        if locked != (previous == LockStatus::Locked) {
+            if locked {
+                delivery.lock_count += 1;
+            } else {
+                delivery.unlock_count += 1;
+            }
+            delivery.last_change_ms = Some(timestamp_ms);
+        }
+    }
+    let event = if locked {
+        SessionStatusEvent {
+            requires_password,
+            ..SessionStatusEvent::lock(state.next_seq(), reason, timestamp_ms)
+        }
+    } else {
+        SessionStatusEvent {
+            status,
+            ..SessionStatusEvent::unlock(state.next_seq(), timestamp_ms)
+        }
+    };
+    delivery.history.push_back(event.clone());
+    while delivery.history.len() > state.replay_on_window_create() {
+        delivery.history.pop_front();
+    }
+    let persisted = state.persist_with().map(|_| PersistedState {
+        locked: Some(locked),
+        since_ms: state.session().since_ms,
+        history: delivery
+            .history
+            .iter()
+            .map(|event| event.status == LockStatus::Locked)
+            .collect(),
+    });
+    delivery.pending.push_back(Notification {
+        status,
+        event,
+        persisted,
+        detected_at,
+    });
+    if delivery.notifying {
+        return;
+    }
+    delivery.notifying = true;
+    let _notifying = Notifying(state);
+    while let Some(notification) = delivery.pending.pop_front() {
+        drop(delivery);
+        announce(app, state, notification);
+        delivery = state.delivery();
+    }
+    delivery.notifying = false;
+}
+
+/// Lets the next delivery run the pending notifications if a hook panics.
+struct Notifying<'a>(&'a SharedState);
+
+impl Drop for Notifying<'_> {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            self.0.delivery().notifying = false;
+        }
+    }
+}
+
+/// Runs the hooks, emit and publish of a delivered event, without holding
+/// the delivery lock.
+fn announce<R: Runtime>(app: &AppHandle<R>, state: &SharedState, notification: Notification) {
+    let Notification {
+        status,
+        event,
+        persisted,
+        detected_at,
+    } = notification;
+    if let (Some(hook), Some(persisted)) = (state.persist_with(), persisted) {
+        hook(&persisted);
+    }
+
+    emit(app, state.event_name(), event);
+    if let Some(hook) = state.on_change() {
+        hook(status);
+    }
+    #[cfg(feature = "async")]
+    state.publish(status);
+    any_change(app);
+    #[cfg(feature = "latency-metrics")]
+    state.record_latency(detected_at.elapsed());
+    #[cfg(not(feature = "latency-metrics"))]
+    let _ = detected_at;
+    match status {
+        LockStatus::Locked => info!("Screen locked"),
+        LockStatus::Unlocked => info!("Screen unlocked"),
+        status => info!("Screen unlocked, {:?}", status),
+    }
+
+    #[cfg(feature = "debug-notifications")]
+    if state.debug_os_notifications() {
+        notify(app, status == LockStatus::Locked);
+    }
+}
+
+#[cfg(feature = "debug-notifications")]
+fn notify<R: Runtime>(app: &AppHandle<R>, locked: bool) {
+    use crate::logging::warn;
+    use tauri_plugin_notification::Notification;
+
+    match app.try_state::<Notification<R>>() {
+        Some(notification) => {
+            let result = notification
+                .builder()
+                .title("Screen lock status")
+                .body(if locked {
+                    "Screen locked"
+                } else {
+                    "Screen unlocked"
+                })
+                .show();
+            if let Err(e) = result {
+                warn!("Failed to show debug notification: {}", e);
+            }
+        }
+        None => warn!("Debug notifications require tauri-plugin-notification to be registered"),
+    }
+}
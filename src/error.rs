@@ -0,0 +1,51 @@
+use serde::Serialize;
+use std::fmt;
+
+pub type Result<T> = std::result::Result<T, ScreenLockError>;
+
+/// Error returned by the plugin commands, `Builder::try_build` and `start`,
+/// and reported by the `error` event.
+///
+/// Serialized as `{ "kind": "dbus_error", "message": "..." }`, with
+/// `message` omitted for the variants that don't carry one.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", content = "message", rename_all = "snake_case")]
+pub enum ScreenLockError {
+    /// The operation isn't supported on this platform.
+    Unsupported,
+    /// No backend could be initialized on this system.
+    BackendUnavailable,
+    /// A D-Bus call failed (Linux).
+    DbusError(String),
+    /// A platform API call failed.
+    PlatformError(String),
+    /// Monitoring hasn't finished initializing yet.
+    NotReady,
+    /// A command argument is out of range.
+    InvalidArgument(String),
+    /// The plugin was stopped with `stop` and can't be started again.
+    Stopped,
+}
+
+impl fmt::Display for ScreenLockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScreenLockError::Unsupported => write!(f, "Not supported on this platform"),
+            ScreenLockError::BackendUnavailable => write!(f, "No screen lock backend is available"),
+            ScreenLockError::DbusError(message) => write!(f, "D-Bus error: {}", message),
+            ScreenLockError::PlatformError(message) => write!(f, "Platform error: {}", message),
+            ScreenLockError::NotReady => write!(f, "Screen lock monitoring is not ready yet"),
+            ScreenLockError::InvalidArgument(message) => write!(f, "Invalid argument: {}", message),
+            ScreenLockError::Stopped => write!(f, "Screen lock monitoring was stopped"),
+        }
+    }
+}
+
+impl std::error::Error for ScreenLockError {}
+
+#[cfg(linux_backend)]
+impl From<zbus::Error> for ScreenLockError {
+    fn from(e: zbus::Error) -> Self {
+        ScreenLockError::DbusError(e.to_string())
+    }
+}
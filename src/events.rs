@@ -0,0 +1,86 @@
+/// Lock and unlock events, see [`SessionStatusEvent`](crate::SessionStatusEvent).
+/// `Builder::event_name` can move them to another channel.
+pub const CHANGE_SESSION_STATUS: &str = "window_screen_lock_status://change_session_status";
+/// Monitoring has started, see [`MonitoringStartedEvent`](crate::MonitoringStartedEvent).
+pub const MONITORING_STARTED: &str = "window_screen_lock_status://monitoring_started";
+/// Any change of the session state, see
+/// [`AnyChangeEvent`](crate::AnyChangeEvent).
+pub const ANY_CHANGE: &str = "window_screen_lock_status://any_change";
+/// A monitoring thread panicked, see [`MonitorErrorEvent`](crate::MonitorErrorEvent).
+pub const MONITOR_ERROR: &str = "window_screen_lock_status://monitor_error";
+/// A backend failed, see [`BackendErrorEvent`](crate::BackendErrorEvent).
+pub const BACKEND_ERROR: &str = "window_screen_lock_status://error";
+/// The screensaver turned on or off, see [`ScreensaverEvent`](crate::ScreensaverEvent).
+pub const SCREENSAVER_CHANGED: &str = "window_screen_lock_status://screensaver_changed";
+/// A lock looks imminent, see [`PreLockEvent`](crate::PreLockEvent).
+pub const PRE_LOCK: &str = "window_screen_lock_status://pre_lock";
+/// The user became present or away, see [`PresenceEvent`](crate::PresenceEvent).
+pub const PRESENCE_CHANGED: &str = "window_screen_lock_status://presence_changed";
+/// The live lock state differs from the restored one, see
+/// [`RestoredEvent`](crate::RestoredEvent).
+pub const RESTORED: &str = "window_screen_lock_status://restored";
+/// Unprocessed backend signals, see [`RawEvent`](crate::RawEvent).
+pub const RAW_EVENT: &str = "window_screen_lock_status://raw_event";
+
+/// Event channels this build can emit on the target it was compiled for.
+#[cfg(all(linux_backend, not(feature = "mock")))]
+pub const SUPPORTED_EVENTS: &[&str] = &[
+    CHANGE_SESSION_STATUS,
+    ANY_CHANGE,
+    MONITORING_STARTED,
+    MONITOR_ERROR,
+    BACKEND_ERROR,
+    SCREENSAVER_CHANGED,
+    PRESENCE_CHANGED,
+    RESTORED,
+    RAW_EVENT,
+];
+
+/// Event channels this build can emit on the target it was compiled for.
+#[cfg(all(windows_backend, not(feature = "mock")))]
+pub const SUPPORTED_EVENTS: &[&str] = &[
+    CHANGE_SESSION_STATUS,
+    ANY_CHANGE,
+    MONITORING_STARTED,
+    MONITOR_ERROR,
+    BACKEND_ERROR,
+    PRE_LOCK,
+    PRESENCE_CHANGED,
+    RESTORED,
+    RAW_EVENT,
+];
+
+/// Event channels this build can emit on the target it was compiled for.
+#[cfg(all(macos_backend, not(feature = "mock")))]
+pub const SUPPORTED_EVENTS: &[&str] = &[
+    CHANGE_SESSION_STATUS,
+    ANY_CHANGE,
+    MONITORING_STARTED,
+    MONITOR_ERROR,
+    BACKEND_ERROR,
+    PRE_LOCK,
+    PRESENCE_CHANGED,
+    RESTORED,
+];
+
+/// Event channels this build can emit with the `mock` feature, which
+/// replaces the platform backends on every target: `simulate` reports lock,
+/// unlock and detail changes, and a failed debounce thread an error.
+#[cfg(feature = "mock")]
+pub const SUPPORTED_EVENTS: &[&str] = &[
+    CHANGE_SESSION_STATUS,
+    ANY_CHANGE,
+    MONITORING_STARTED,
+    BACKEND_ERROR,
+];
+
+/// Event channels this build can emit on the target it was compiled for.
+#[cfg(not(any(linux_backend, macos_backend, windows_backend, feature = "mock")))]
+pub const SUPPORTED_EVENTS: &[&str] = &[];
+
+/// Returns [`SUPPORTED_EVENTS`]. Unlike the `get_capabilities` command this
+/// doesn't depend on which backend is running, only on the compiled target
+/// and the `mock` feature: a channel missing here never fires.
+pub fn supported_events() -> &'static [&'static str] {
+    SUPPORTED_EVENTS
+}
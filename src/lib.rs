@@ -1,261 +1,730 @@
-// Add tracing to dependencies in Cargo.toml
-use tauri::Emitter;
-use tracing::{info, warn};
-#[cfg(target_os = "linux")]
-use zbus::{blocking::Connection, dbus_proxy};
-
-#[cfg(target_os = "windows")]
-use windows::{
-    core::*,
-    Win32::Foundation::*,
-    Win32::System::{
-        LibraryLoader::*,
-        RemoteDesktop::{WTSRegisterSessionNotification, NOTIFY_FOR_ALL_SESSIONS},
-    },
-    Win32::UI::Input::KeyboardAndMouse::GetActiveWindow,
-    Win32::UI::WindowsAndMessaging::*,
-};
-
-#[cfg(target_os = "macos")]
-extern crate core_foundation;
-#[cfg(target_os = "macos")]
-extern crate core_graphics;
+#[cfg(feature = "commands")]
+mod commands;
+// Partly unused without a platform backend to report changes, and with the
+// `mock` backend, which has no thread to supervise and no state to read at
+// startup. The same goes for `supervisor`.
+#[cfg_attr(
+    any(
+        feature = "mock",
+        not(any(linux_backend, macos_backend, windows_backend))
+    ),
+    allow(dead_code)
+)]
+mod dispatch;
+mod error;
+pub mod events;
+mod logging;
+// Built but never started with the `mock` feature.
+#[cfg(linux_backend)]
+#[cfg_attr(feature = "mock", allow(dead_code))]
+mod linux;
+#[cfg(macos_backend)]
+#[cfg_attr(feature = "mock", allow(dead_code))]
+mod macos;
+#[cfg(feature = "mock")]
+mod mock;
+mod models;
+#[cfg(any(linux_backend, macos_backend, windows_backend))]
+#[cfg_attr(feature = "mock", allow(dead_code))]
+mod presence;
+mod state;
+#[cfg(feature = "async")]
+mod stream;
+#[cfg_attr(
+    any(
+        feature = "mock",
+        not(any(linux_backend, macos_backend, windows_backend))
+    ),
+    allow(dead_code)
+)]
+mod supervisor;
+#[cfg(windows_backend)]
+#[cfg_attr(feature = "mock", allow(dead_code))]
+mod windows;
+#[cfg(any(windows_backend, test))]
+#[cfg_attr(feature = "mock", allow(dead_code))]
+mod wts;
 
-#[cfg(target_os = "macos")]
-use core_foundation::{base::TCFType, base::ToVoid, dictionary::CFDictionary, string::CFString};
+pub use error::{Result, ScreenLockError};
+pub use events::{supported_events, SUPPORTED_EVENTS};
+#[cfg(feature = "mock")]
+pub use mock::simulate;
+pub use models::{
+    AnyChangeEvent, BackendErrorEvent, BackendKind, Capabilities, Channel, FullState, LockMetrics,
+    LockReason, LockStatus, MonitorErrorEvent, MonitoringStartedEvent, PersistedState,
+    PreLockEvent, PresenceEvent, RawEvent, RawValue, RestoredEvent, ScreensaverEvent, SessionInfo,
+    SessionStatusEvent, Stats,
+};
+pub use state::{
+    DEFAULT_IDLE_THRESHOLD_MS, DEFAULT_POLL_INTERVAL_MS, MAX_UNLOCK_DEBOUNCE_MS,
+    MIN_IDLE_THRESHOLD_MS, MIN_POLL_INTERVAL_MS,
+};
+#[cfg(feature = "async")]
+pub use stream::{subscribe, wait_for};
+pub use supervisor::DEFAULT_MAX_RESTARTS;
 
+use crate::logging::warn;
+use state::{ChangeHook, EmitFilter, PersistHook, RawReadHook, SharedState};
+use std::any::Any;
 use std::sync::OnceLock;
-use std::thread;
 use std::time::Duration;
 use tauri::{
-    plugin::{Builder, TauriPlugin},
-    AppHandle, Runtime,
+    plugin::{Builder as PluginBuilder, TauriPlugin},
+    webview::PageLoadEvent,
+    AppHandle, Emitter, Manager, Runtime,
 };
 
-#[cfg(target_os = "macos")]
-extern "C" {
-    fn CGSessionCopyCurrentDictionary() -> core_foundation::dictionary::CFDictionaryRef;
-}
+#[cfg(linux_backend)]
+use linux::requires_password;
+#[cfg(macos_backend)]
+use macos::requires_password;
+#[cfg(windows_backend)]
+use windows::requires_password;
 
-#[cfg(target_os = "linux")]
-#[dbus_proxy(
-    interface = "org.freedesktop.login1.Session",
-    default_service = "org.freedesktop.login1",
-    default_path = "/org/freedesktop/login1/session/auto"
-)]
-trait Session {
-    #[dbus_proxy(property)]
-    fn locked_hint(&self) -> zbus::Result<bool>;
+#[cfg(not(any(linux_backend, macos_backend, windows_backend)))]
+#[cfg_attr(not(any(feature = "commands", feature = "mock")), allow(dead_code))]
+fn requires_password() -> Option<bool> {
+    None
 }
 
-#[cfg(target_os = "windows")]
-fn register_session_notification(hwnd: HWND) {
-    unsafe {
-        let _ = WTSRegisterSessionNotification(hwnd, NOTIFY_FOR_ALL_SESSIONS);
-    }
+/// Formerly the app handle the monitoring threads emitted through.
+///
+/// Each plugin instance now keeps its own handle and state, so several apps
+/// in one process no longer share events. The plugin still sets this to the
+/// handle of the first app set up with the default runtime, before any
+/// monitoring thread starts, for code that reads it, but never reads it
+/// itself.
+#[deprecated(note = "the plugin keeps its app handle per instance; this is no longer used")]
+pub static WINDOW_TAURI: OnceLock<AppHandle> = OnceLock::new();
+
+/// Configures and builds the plugin. [`init`] builds it with the defaults.
+pub struct Builder {
+    unlock_debounce: Option<Duration>,
+    poll_interval: Duration,
+    replay_on_window_create: usize,
+    prefer_backend: Option<BackendKind>,
+    max_restarts: u32,
+    auto_start: bool,
+    annotate_requires_password: bool,
+    raw_events: bool,
+    any_change_events: bool,
+    initial_status: bool,
+    detailed_status: bool,
+    channels: Vec<Channel>,
+    on_raw_read: Option<RawReadHook>,
+    on_change: Option<ChangeHook>,
+    event_name: Option<String>,
+    emit_to: Option<String>,
+    emit_filter: Option<EmitFilter>,
+    persist_with: Option<PersistHook>,
+    restore_from: Option<Box<dyn FnOnce() -> Option<PersistedState> + Send>>,
+    #[cfg(feature = "debug-notifications")]
+    debug_os_notifications: bool,
+    #[cfg(linux_backend)]
+    logind: linux::Logind,
+    #[cfg(windows_backend)]
+    #[cfg_attr(feature = "mock", allow(dead_code))]
+    current_session_only: bool,
+    #[cfg(windows_backend)]
+    #[cfg_attr(feature = "mock", allow(dead_code))]
+    remote_session_events: bool,
 }
 
-#[cfg(target_os = "windows")]
-extern "system" fn wndproc(window: HWND, message: u32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
-    unsafe {
-        match message as u32 {
-            _ => DefWindowProcA(window, message, wparam, lparam),
+impl Default for Builder {
+    fn default() -> Self {
+        Self {
+            unlock_debounce: None,
+            poll_interval: Duration::from_millis(DEFAULT_POLL_INTERVAL_MS),
+            replay_on_window_create: 1,
+            prefer_backend: None,
+            max_restarts: DEFAULT_MAX_RESTARTS,
+            auto_start: true,
+            annotate_requires_password: false,
+            raw_events: false,
+            any_change_events: false,
+            initial_status: true,
+            detailed_status: false,
+            channels: vec![Channel::Lock],
+            on_raw_read: None,
+            on_change: None,
+            event_name: None,
+            emit_to: None,
+            emit_filter: None,
+            persist_with: None,
+            restore_from: None,
+            #[cfg(feature = "debug-notifications")]
+            debug_os_notifications: false,
+            #[cfg(linux_backend)]
+            logind: linux::Logind::default(),
+            #[cfg(windows_backend)]
+            current_session_only: false,
+            #[cfg(windows_backend)]
+            remote_session_events: false,
         }
     }
 }
 
-pub static WINDOW_TAURI: OnceLock<AppHandle> = OnceLock::new();
+impl Builder {
+    pub fn new() -> Self {
+        Self::default()
+    }
 
-pub fn init<R: Runtime>() -> TauriPlugin<R> {
-    #[cfg(target_os = "windows")]
+    /// Holds back `unlock` events until the session has stayed unlocked for
+    /// `debounce`, so an unlock/re-lock flicker during the authentication
+    /// animation is not reported. If the session locks again within the
+    /// window the pending unlock is dropped, and since the frontend never saw
+    /// the session unlocked, the re-lock isn't reported either.
+    ///
+    /// Only unlocks are delayed: `lock` events are always delivered
+    /// immediately. Disabled by default, and `Duration::ZERO` disables it
+    /// again. Otherwise it must be between 1ms and
+    /// [`MAX_UNLOCK_DEBOUNCE_MS`], see [`Builder::try_build`].
+    pub fn unlock_debounce(mut self, debounce: Duration) -> Self {
+        self.unlock_debounce = Some(debounce).filter(|debounce| !debounce.is_zero());
+        self
+    }
+
+    /// How often the idle time is polled for [`Channel::Presence`], and for
+    /// pre-lock warnings on Windows. Lock changes arrive as signals on every
+    /// platform and don't depend on it. Defaults to
+    /// [`DEFAULT_POLL_INTERVAL_MS`]; shorter intervals mean earlier presence
+    /// changes and warnings but more wakeups, and anything below
+    /// [`MIN_POLL_INTERVAL_MS`] is raised to it.
+    pub fn poll_interval(mut self, interval: Duration) -> Self {
+        self.poll_interval = interval;
+        self
+    }
+
+    /// Re-emits the last `count` status events to every window once its page
+    /// has loaded, so windows opened mid-session learn the current state
+    /// without waiting for the next transition. Defaults to `1`, i.e. just the
+    /// current state; `0` disables the replay.
+    pub fn replay_on_window_create(mut self, count: usize) -> Self {
+        self.replay_on_window_create = count;
+        self
+    }
+
+    /// Tries `backend` first instead of the automatically detected one,
+    /// falling back to detection if it fails to initialize. See
+    /// [`BackendKind`] for the backends available on each platform.
+    pub fn prefer_backend(mut self, backend: BackendKind) -> Self {
+        self.prefer_backend = Some(backend);
+        self
+    }
+
+    /// Restarts the monitoring thread up to `max` times after a panic, with
+    /// an exponential backoff starting at one second. Every panic is also
+    /// reported through the `window_screen_lock_status://monitor_error`
+    /// event. Defaults to [`DEFAULT_MAX_RESTARTS`]; `0` never restarts.
+    pub fn restart_on_panic(mut self, max: u32) -> Self {
+        self.max_restarts = max;
+        self
+    }
+
+    /// With `false`, the plugin and its commands are registered but no
+    /// monitoring thread starts until [`start`] is called, e.g. once the user
+    /// has logged in to the app. Until then no lock events are emitted and
+    /// `await_ready` keeps waiting. Defaults to `true`.
+    pub fn auto_start(mut self, enabled: bool) -> Self {
+        self.auto_start = enabled;
+        self
+    }
+
+    /// Adds `requires_password` to lock events, read from the user's settings
+    /// at the time of the lock. See [`SessionInfo::requires_password`] for
+    /// what is read on each platform. Off by default.
+    pub fn annotate_requires_password(mut self, enabled: bool) -> Self {
+        self.annotate_requires_password = enabled;
+        self
+    }
+
+    /// Emits every signal received from the backend on
+    /// `window_screen_lock_status://raw_event`, even when the lock state did
+    /// not change: the `LockedHint`/`ActiveChanged` value on Linux and the
+    /// `WM_WTSSESSION_CHANGE` code on Windows. Meant for diagnosing events
+    /// that never fire; don't build app logic on it. Off by default.
+    pub fn raw_events(mut self, enabled: bool) -> Self {
+        self.raw_events = enabled;
+        self
+    }
+
+    /// Emits the full session state, as returned by `get_full_state`, on
+    /// `window_screen_lock_status://any_change` whenever the lock, screensaver
+    /// or display state changes. For frontends that refresh the same way on
+    /// any change. Off by default.
+    pub fn any_change_events(mut self, enabled: bool) -> Self {
+        self.any_change_events = enabled;
+        self
+    }
+
+    /// Emits the lock state read when monitoring starts as a regular lock or
+    /// unlock event, so listeners see the state the app was launched into
+    /// instead of waiting for the first transition. With `false` only
+    /// transitions are emitted. On by default.
+    ///
+    /// Windows can't query the state, so there the first event is always a
+    /// transition.
+    pub fn initial_status(mut self, enabled: bool) -> Self {
+        self.initial_status = enabled;
+        self
+    }
+
+    /// Reports an unlocked session as [`LockStatus::ScreensaverActive`] while
+    /// the screensaver runs and as [`LockStatus::DisplaySleep`] while the
+    /// display is off, where the platform detects it: the screensaver on
+    /// macOS and on GNOME with logind, the display on Windows. Elsewhere the
+    /// status stays `Unlocked`. Off by default, so existing listeners only
+    /// see `Locked` and `Unlocked`.
+    pub fn detailed_status(mut self, enabled: bool) -> Self {
+        self.detailed_status = enabled;
+        self
+    }
+
+    /// Calls `hook` with every lock state a backend reads, before debouncing
+    /// and deduplication: on every signal, and once for the initial state
+    /// on Linux and macOS. The `Duration` is the time since the backend could first
+    /// have seen that state, as in the `get_stats` command.
+    ///
+    /// `hook` runs on the monitoring thread and must not block.
+    pub fn on_raw_read<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(BackendKind, LockStatus, Duration) + Send + Sync + 'static,
     {
-        thread::spawn(|| unsafe {
-            info!("Starting new thread for Windows screen lock monitoring...");
-            let instance = GetModuleHandleA(None).unwrap();
-            debug_assert!(instance.0 != 0);
-
-            let window_class = s!("window");
-
-            let wc = WNDCLASSA {
-                hCursor: LoadCursorW(None, IDC_ARROW).unwrap(),
-                hInstance: instance.into(),
-                lpszClassName: window_class,
-                style: CS_HREDRAW | CS_VREDRAW,
-                lpfnWndProc: Some(wndproc),
-                ..Default::default()
-            };
-
-            let atom = RegisterClassA(&wc);
-            debug_assert!(atom != 0);
-
-            CreateWindowExA(
-                WINDOW_EX_STYLE::default(),
-                window_class,
-                s!("Window"),
-                WS_OVERLAPPEDWINDOW | WS_VISIBLE,
-                CW_USEDEFAULT,
-                CW_USEDEFAULT,
-                CW_USEDEFAULT,
-                CW_USEDEFAULT,
-                None,
-                None,
-                instance,
-                Some(std::ptr::null()),
-            );
+        self.on_raw_read = Some(Box::new(hook));
+        self
+    }
 
-            let hwnd = GetActiveWindow();
-            ShowWindow(*&hwnd, SW_HIDE);
-
-            let mut message = MSG::default();
-            register_session_notification(hwnd);
-            while GetMessageA(&mut message, HWND(0), 0, 0).into() {
-                if message.message == WM_WTSSESSION_CHANGE {
-                    TranslateMessage(&message);
-                    DispatchMessageW(&message);
-
-                    match message.wParam.0 as u32 {
-                        WTS_SESSION_LOCK => match WINDOW_TAURI.get() {
-                            Ok(handle) => {
-                                let _ = handle.emit(
-                                    "window_screen_lock_status://change_session_status",
-                                    "lock",
-                                );
-                                info!("Screen locked");
-                            }
-                            Err(e) => warn!("Failed to get WINDOW_TAURI handle: {}", e),
-                        },
-                        WTS_SESSION_UNLOCK => match WINDOW_TAURI.get() {
-                            Ok(handle) => {
-                                let _ = handle.emit(
-                                    "window_screen_lock_status://change_session_status",
-                                    "unlock",
-                                );
-                                info!("Screen unlocked");
-                            }
-                            Err(e) => warn!("Failed to get WINDOW_TAURI handle: {}", e),
-                        },
-                        _ => {}
-                    }
-                }
-                thread::sleep(Duration::from_millis(1000));
-            }
-        });
+    /// Calls `hook` with every delivered lock and unlock, right after the
+    /// `change_session_status` event is emitted, so Rust code can react
+    /// without listening to the event. Debouncing and deduplication apply as
+    /// for the event.
+    ///
+    /// `hook` runs on the monitoring thread and must not block; use
+    /// `subscribe` with the `async` feature to handle transitions
+    /// asynchronously instead.
+    pub fn on_change<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(LockStatus) + Send + Sync + 'static,
+    {
+        self.on_change = Some(Box::new(hook));
+        self
+    }
+
+    /// Emits lock and unlock events on `name` instead of
+    /// [`events::CHANGE_SESSION_STATUS`], e.g. to namespace them per app or
+    /// tell several plugin instances apart. The other channels keep their
+    /// names. `get_supported_events` reports the configured name.
+    ///
+    /// Tauri only accepts alphanumerics, `-`, `/`, `:` and `_` in event
+    /// names; [`Builder::try_build`] rejects any other name.
+    pub fn event_name(mut self, name: impl Into<String>) -> Self {
+        self.event_name = Some(name.into());
+        self
+    }
+
+    /// Only delivers events to the window labelled `label`, instead of
+    /// broadcasting to all of them; takes precedence over
+    /// [`Builder::emit_filter`]. Events sent while no such window exists are
+    /// logged with a warning, since Tauri drops them.
+    pub fn emit_to(mut self, label: impl Into<String>) -> Self {
+        self.emit_to = Some(label.into());
+        self
     }
 
-    #[cfg(target_os = "linux")]
+    /// Only delivers events to the windows and webviews whose label `filter`
+    /// accepts, instead of broadcasting to all of them. Replays on window
+    /// load are filtered the same way.
+    ///
+    /// Tauri applies the filter to listeners registered on a window or
+    /// webview, e.g. `getCurrentWebviewWindow().listen(...)`. Listeners
+    /// registered with the global `listen` from `@tauri-apps/api/event`
+    /// target any window and keep receiving every event.
+    pub fn emit_filter<F>(mut self, filter: F) -> Self
+    where
+        F: Fn(&str) -> bool + Send + Sync + 'static,
     {
-        thread::spawn(move || {
-            info!("Starting new thread for Linux screen lock monitoring...");
-            let mut flg = false;
-            loop {
-                let conn = match Connection::system() {
-                    Ok(conn) => conn,
-                    Err(e) => {
-                        warn!("Failed to establish system connection: {}", e);
-                        break;
+        self.emit_filter = Some(Box::new(filter));
+        self
+    }
+
+    /// Calls `hook` with the lock state and replay history after every
+    /// delivered lock or unlock, to save them e.g. with
+    /// `tauri-plugin-store`. `hook` runs on the monitoring thread and must
+    /// not block.
+    pub fn persist_with<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&PersistedState) + Send + Sync + 'static,
+    {
+        self.persist_with = Some(Box::new(hook));
+        self
+    }
+
+    /// Loads the state saved with [`Builder::persist_with`] once, when the
+    /// plugin is set up. The restored state is reported by `get_full_state`
+    /// and replayed to new windows until a backend reads the live state; if
+    /// that differs, a [`RestoredEvent`] and a regular lock or unlock event
+    /// are emitted.
+    pub fn restore_from<F>(mut self, load: F) -> Self
+    where
+        F: FnOnce() -> Option<PersistedState> + Send + 'static,
+    {
+        self.restore_from = Some(Box::new(load));
+        self
+    }
+
+    /// Turns an event channel on or off. Only [`Channel::Lock`] is enabled by
+    /// default. The backend behind a disabled channel isn't initialized at
+    /// all: with `Lock` disabled, no monitoring thread is started and no
+    /// D-Bus subscription or session notification is registered.
+    pub fn enable_channel(mut self, channel: Channel, enabled: bool) -> Self {
+        self.channels.retain(|existing| *existing != channel);
+        if enabled {
+            self.channels.push(channel);
+        }
+        self
+    }
+
+    /// Posts a native desktop notification on every lock/unlock, to confirm
+    /// detection works without a frontend. Off by default.
+    ///
+    /// Needs `tauri-plugin-notification` to be registered in the app, and
+    /// notification permission on macOS and Windows.
+    #[cfg(feature = "debug-notifications")]
+    pub fn debug_os_notifications(mut self, enabled: bool) -> Self {
+        self.debug_os_notifications = enabled;
+        self
+    }
+
+    /// Reaches logind through `connection` instead of the system bus, e.g. a
+    /// mock bus in tests or a sandbox bus. Linux only; the screensaver
+    /// fallbacks keep using the session bus.
+    #[cfg(linux_backend)]
+    pub fn dbus_connection(mut self, connection: zbus::blocking::Connection) -> Self {
+        self.logind.bus = linux::Bus::Connection(connection);
+        self
+    }
+
+    /// Like [`Builder::dbus_connection`], but connects to the bus at
+    /// `address`, e.g. `unix:path=/run/user/1000/bus`. Linux only.
+    #[cfg(linux_backend)]
+    pub fn dbus_address(mut self, address: impl Into<String>) -> Self {
+        self.logind.bus = linux::Bus::Address(address.into());
+        self
+    }
+
+    /// Reads `LockedHint` from the service `name` instead of
+    /// `org.freedesktop.login1`, for logind forks that register under
+    /// another name. Linux only.
+    #[cfg(linux_backend)]
+    pub fn dbus_service(mut self, name: impl Into<String>) -> Self {
+        self.logind.service = name.into();
+        self
+    }
+
+    /// Reads `LockedHint` through `interface` instead of
+    /// `org.freedesktop.login1.Session`. Linux only.
+    #[cfg(linux_backend)]
+    pub fn dbus_interface(mut self, interface: impl Into<String>) -> Self {
+        self.logind.interface = interface.into();
+        self
+    }
+
+    /// Follows every user session on a seat, for shared machines with
+    /// several seats or fast user switching, instead of only the session
+    /// the app runs in. The session counts as unlocked while any seat shows
+    /// an unlocked session, and as locked otherwise, including on the login
+    /// screen. Sessions started and closed later are picked up. Linux only;
+    /// defaults to the current session.
+    #[cfg(linux_backend)]
+    pub fn all_sessions(mut self, enabled: bool) -> Self {
+        self.logind.all_sessions = enabled;
+        self
+    }
+
+    /// Only reports lock changes of the session the app runs in, instead of
+    /// any session on the machine. Useful on multi-user RDP hosts. Windows
+    /// only; defaults to all sessions.
+    #[cfg(windows_backend)]
+    pub fn current_session_only(mut self, enabled: bool) -> Self {
+        self.current_session_only = enabled;
+        self
+    }
+
+    /// Also reports a remote desktop disconnect as a lock and a reconnect
+    /// as an unlock, e.g. to hide secrets once an RDP client goes away.
+    /// Logons and logoffs are always reported, as an unlock and a lock.
+    /// Windows only; off by default.
+    #[cfg(windows_backend)]
+    pub fn remote_session_events(mut self, enabled: bool) -> Self {
+        self.remote_session_events = enabled;
+        self
+    }
+
+    /// Builds the plugin, logging a warning for options that
+    /// [`Builder::try_build`] would reject.
+    pub fn build<R: Runtime>(self) -> TauriPlugin<R> {
+        if let Err(e) = self.validate() {
+            warn!("Invalid plugin configuration: {}", e);
+        }
+        self.build_plugin()
+    }
+
+    /// Builds the plugin, or fails with [`ScreenLockError::InvalidArgument`]
+    /// if the event name is invalid or a duration option is out of range:
+    /// an unlock debounce under 1ms, which would be rounded down to none, or
+    /// over [`MAX_UNLOCK_DEBOUNCE_MS`]. A poll interval under
+    /// [`MIN_POLL_INTERVAL_MS`] is raised to it and only logged.
+    pub fn try_build<R: Runtime>(self) -> Result<TauriPlugin<R>> {
+        self.validate()?;
+        Ok(self.build_plugin())
+    }
+
+    fn validate(&self) -> Result<()> {
+        // The characters Tauri accepts in event names.
+        let valid_char = |c: char| c.is_alphanumeric() || matches!(c, '-' | '/' | ':' | '_');
+        if let Some(name) = &self.event_name {
+            if name.is_empty() || !name.chars().all(valid_char) {
+                return Err(ScreenLockError::InvalidArgument(format!(
+                    "Event name {:?} may only contain alphanumerics, '-', '/', ':' and '_'",
+                    name
+                )));
+            }
+        }
+        if self.poll_interval < Duration::from_millis(MIN_POLL_INTERVAL_MS) {
+            warn!(
+                "Poll interval of {:?} is too short, using {}ms",
+                self.poll_interval, MIN_POLL_INTERVAL_MS
+            );
+        }
+        if let Some(debounce) = self.unlock_debounce {
+            if debounce < Duration::from_millis(1) {
+                return Err(ScreenLockError::InvalidArgument(format!(
+                    "Unlock debounce must be at least 1ms, got {:?}",
+                    debounce
+                )));
+            }
+            if debounce > Duration::from_millis(MAX_UNLOCK_DEBOUNCE_MS) {
+                return Err(ScreenLockError::InvalidArgument(format!(
+                    "Unlock debounce must be at most {}ms, got {}ms",
+                    MAX_UNLOCK_DEBOUNCE_MS,
+                    debounce.as_millis()
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    fn build_plugin<R: Runtime>(self) -> TauriPlugin<R> {
+        let builder = PluginBuilder::new("window-screen-lock-status")
+            .setup(move |app, _api| {
+                if let Some(handle) = (app as &dyn Any).downcast_ref::<AppHandle>() {
+                    #[allow(deprecated)]
+                    let _ = WINDOW_TAURI.set(handle.clone());
+                }
+                let state = SharedState::new();
+                state.set_unlock_debounce(self.unlock_debounce);
+                state.set_poll_interval(self.poll_interval);
+                state.set_replay_on_window_create(self.replay_on_window_create);
+                state.set_preferred_backend(self.prefer_backend);
+                state.set_annotate_requires_password(self.annotate_requires_password);
+                state.set_raw_events(self.raw_events);
+                state.set_any_change_events(self.any_change_events);
+                state.set_initial_status(self.initial_status);
+                state.set_detailed_status(self.detailed_status);
+                state.set_screensaver_events(self.channels.contains(&Channel::Screensaver));
+                state.set_presence_events(self.channels.contains(&Channel::Presence));
+                if let Some(hook) = self.on_raw_read {
+                    state.set_on_raw_read(hook);
+                }
+                if let Some(hook) = self.on_change {
+                    state.set_on_change(hook);
+                }
+                if let Some(name) = self.event_name {
+                    state.set_event_name(name);
+                }
+                if let Some(label) = self.emit_to {
+                    state.set_emit_target(label);
+                }
+                if let Some(filter) = self.emit_filter {
+                    state.set_emit_filter(filter);
+                }
+                if let Some(hook) = self.persist_with {
+                    state.set_persist_with(hook);
+                }
+                if let Some(persisted) = self.restore_from.and_then(|load| load()) {
+                    dispatch::restore(&state, persisted);
+                }
+                #[cfg(feature = "debug-notifications")]
+                state.set_debug_os_notifications(self.debug_os_notifications);
+                app.manage(state);
+                #[cfg(linux_backend)]
+                app.manage(linux::Switch::default());
+                #[cfg(linux_backend)]
+                app.manage(self.logind.clone());
+                #[cfg(macos_backend)]
+                app.manage(macos::RunLoops::default());
+                #[cfg(windows_backend)]
+                app.manage(windows::MessageLoop::default());
+
+                // Without a lock channel there is nothing to wait for.
+                if !self.channels.contains(&Channel::Lock) {
+                    app.state::<SharedState>().set_ready(false);
+                }
+                let handle = app.clone();
+                let start = move || {
+                    // Starts nothing on an unsupported platform.
+                    #[cfg_attr(
+                        not(any(linux_backend, macos_backend, windows_backend, feature = "mock")),
+                        allow(unused_variables)
+                    )]
+                    let app = &handle;
+                    if self.channels.contains(&Channel::PreLock) {
+                        #[cfg(all(windows_backend, not(feature = "mock")))]
+                        {
+                            let handle = app.clone();
+                            supervisor::spawn_named(
+                                app,
+                                "screen-lock-pre-lock",
+                                false,
+                                move || windows::watch_pre_lock(handle),
+                            );
+                        }
+                        #[cfg(all(macos_backend, not(feature = "mock")))]
+                        {
+                            let handle = app.clone();
+                            supervisor::spawn_named(
+                                app,
+                                "screen-lock-pre-lock",
+                                false,
+                                move || macos::watch_pre_lock(handle),
+                            );
+                        }
                     }
-                };
 
-                let proxy = match SessionProxyBlocking::new(&conn) {
-                    Ok(proxy) => proxy,
-                    Err(e) => {
-                        warn!("Failed to create session proxy: {}", e);
-                        break;
+                    #[cfg(all(
+                        any(linux_backend, macos_backend, windows_backend),
+                        not(feature = "mock")
+                    ))]
+                    if self.channels.contains(&Channel::Presence) {
+                        #[cfg(linux_backend)]
+                        let idle_time = linux::idle_time_reader();
+                        #[cfg(macos_backend)]
+                        let idle_time = macos::idle_time;
+                        #[cfg(windows_backend)]
+                        let idle_time = windows::idle_time;
+                        let handle = app.clone();
+                        supervisor::spawn_named(app, "screen-lock-presence", false, move || {
+                            presence::watch(handle, idle_time)
+                        });
                     }
-                };
 
-                let mut property = proxy.receive_locked_hint_changed();
-
-                match property.next() {
-                    Some(pro) => {
-                        let current_property = match pro.get() {
-                            Ok(prop) => prop,
-                            Err(e) => {
-                                warn!("Failed to get property: {}", e);
-                                break;
-                            }
-                        };
-
-                        if flg != current_property {
-                            flg = current_property;
-                            match WINDOW_TAURI.get() {
-                                Some(handle) => {
-                                    if current_property {
-                                        let _ = handle.emit(
-                                            "window_screen_lock_status://change_session_status",
-                                            "lock",
-                                        );
-                                        info!("Screen locked");
-                                    } else {
-                                        let _ = handle.emit(
-                                            "window_screen_lock_status://change_session_status",
-                                            "unlock",
-                                        );
-                                        info!("Screen unlocked");
-                                    }
-                                }
-                                None => {
-                                    warn!("Failed to get WINDOW_TAURI handle");
-                                    break;
-                                }
-                            }
+                    if self.channels.contains(&Channel::Lock) {
+                        #[cfg(feature = "mock")]
+                        mock::monitor(app);
+
+                        #[cfg(all(windows_backend, not(feature = "mock")))]
+                        {
+                            let current_session_only = self.current_session_only;
+                            let remote_session_events = self.remote_session_events;
+                            supervisor::spawn(app.clone(), self.max_restarts, move |app| {
+                                windows::monitor(app, current_session_only, remote_session_events)
+                            });
+                        }
+
+                        #[cfg(all(linux_backend, not(feature = "mock")))]
+                        {
+                            let logind = self.logind;
+                            supervisor::spawn(app.clone(), self.max_restarts, move |app| {
+                                linux::monitor(app, &logind)
+                            });
+                        }
+
+                        #[cfg(all(macos_backend, not(feature = "mock")))]
+                        {
+                            supervisor::spawn(app.clone(), self.max_restarts, macos::monitor);
                         }
                     }
-                    None => {
-                        warn!("No property changes received");
-                        break;
-                    }
+                };
+                if self.auto_start {
+                    start();
+                } else {
+                    app.state::<SharedState>().set_starter(Box::new(start));
                 }
-                thread::sleep(Duration::from_millis(1000));
-            }
-        });
-    }
 
-    #[cfg(target_os = "macos")]
-    {
-        thread::spawn(move || {
-            info!("Starting new thread for macOS screen lock monitoring...");
-            let mut flg = false;
-            loop {
-                unsafe {
-                    let session_dictionary_ref = CGSessionCopyCurrentDictionary();
-                    let session_dictionary: CFDictionary =
-                        CFDictionary::wrap_under_create_rule(session_dictionary_ref);
-                    let mut current_session_property = false;
-                    match session_dictionary
-                        .find(CFString::new("CGSSessionScreenIsLocked").to_void())
-                    {
-                        None => current_session_property = false,
-                        Some(_) => current_session_property = true,
-                    }
-                    if flg != current_session_property {
-                        flg = current_session_property;
-                        match WINDOW_TAURI.get() {
-                            Some(handle) => {
-                                if current_session_property {
-                                    let _ = handle.emit(
-                                        "window_screen_lock_status://change_session_status",
-                                        "lock",
-                                    );
-                                    info!("Screen locked");
-                                } else {
-                                    let _ = handle.emit(
-                                        "window_screen_lock_status://change_session_status",
-                                        "unlock",
-                                    );
-                                    info!("Screen unlocked");
-                                }
-                            }
-                            None => {
-                                warn!("Failed to get WINDOW_TAURI handle");
-                                break;
-                            }
-                        }
+                Ok(())
+            })
+            .on_page_load(|webview, payload| {
+                let state = webview.state::<SharedState>();
+                if matches!(payload.event(), PageLoadEvent::Finished)
+                    && state.emits_to(webview.label())
+                {
+                    for event in dispatch::replay_events(&state) {
+                        let _ = webview.emit_to(webview.label(), state.event_name(), event);
                     }
-                    thread::sleep(Duration::from_millis(1000));
                 }
-            }
-        });
+            });
+        #[cfg(feature = "commands")]
+        let builder = builder.invoke_handler(tauri::generate_handler![
+            commands::set_idle_threshold,
+            commands::get_idle_threshold,
+            commands::get_active_backend,
+            commands::set_active_backend,
+            commands::get_capabilities,
+            commands::get_full_state,
+            commands::get_session_info,
+            commands::await_ready,
+            commands::get_supported_events,
+            commands::get_stats,
+            commands::get_lock_metrics,
+            commands::get_lock_status
+        ]);
+        builder.build()
+    }
+}
+
+pub fn init<R: Runtime>() -> TauriPlugin<R> {
+    Builder::new().build()
+}
+
+/// Starts monitoring for a plugin built with [`Builder::auto_start`]
+/// `(false)`. Only the first call starts the monitoring threads; later calls
+/// and calls with auto-start enabled do nothing.
+///
+/// Fails with [`ScreenLockError::Stopped`] after [`stop`], since a stopped
+/// plugin can't be started again.
+///
+/// Panics if the plugin isn't registered on `manager`.
+pub fn start<R: Runtime, M: Manager<R>>(manager: &M) -> Result<()> {
+    let state = manager.state::<SharedState>();
+    if state.is_stopped() {
+        return Err(ScreenLockError::Stopped);
+    }
+    if let Some(start) = state.take_starter() {
+        start();
+    }
+    Ok(())
+}
+
+/// Stops monitoring for good: the monitoring threads release their OS
+/// subscriptions and exit, and neither restart nor emit further events. A
+/// pending debounced unlock is dropped. The plugin can't be started again
+/// in the same app, where [`start`] then fails, so this is meant for
+/// teardown, e.g. at the end of an integration test. Calling it again does
+/// nothing.
+///
+/// Returns right away, without waiting for the threads to exit.
+///
+/// Panics if the plugin isn't registered on `manager`.
+pub fn stop<R: Runtime, M: Manager<R>>(manager: &M) {
+    let app = manager.app_handle();
+    let state = app.state::<SharedState>();
+    if !state.stop() {
+        state.wake();
+        #[cfg(linux_backend)]
+        linux::stop(app);
+        #[cfg(macos_backend)]
+        macos::stop(app);
+        #[cfg(windows_backend)]
+        windows::stop(app);
     }
-    Builder::new("window_screen_lock_status").build()
 }
@@ -0,0 +1,1205 @@
+use crate::dispatch;
+#[cfg(feature = "commands")]
+use crate::error::Result;
+use crate::error::ScreenLockError;
+use crate::logging::{info, warn};
+use crate::models::{BackendKind, RawValue};
+use crate::state::{lock, SharedState};
+use crate::supervisor;
+use std::collections::HashMap;
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Manager, Runtime};
+use zbus::blocking::{Connection, MessageIterator, Proxy};
+use zbus::message::Type as MessageType;
+use zbus::zvariant::{OwnedObjectPath, OwnedValue};
+use zbus::MatchRule;
+
+/// Session of the calling process, resolved by logind. Only used when the
+/// session can't be looked up, since logind resolves it for each call and
+/// may pick none, e.g. for a process started outside the session.
+const AUTO_SESSION_PATH: &str = "/org/freedesktop/login1/session/auto";
+/// logind's manager object, used to look up the session.
+const MANAGER_PATH: &str = "/org/freedesktop/login1";
+const MANAGER_INTERFACE: &str = "org.freedesktop.login1.Manager";
+const PROPERTIES_INTERFACE: &str = "org.freedesktop.DBus.Properties";
+
+/// Consecutive attempts a backend may fail to subscribe before the monitor
+/// falls back to the next available one.
+const MAX_FAILURES: u32 = 3;
+
+/// Bus used to reach logind.
+#[derive(Clone, Default)]
+pub(crate) enum Bus {
+    #[default]
+    System,
+    Connection(Connection),
+    Address(String),
+}
+
+impl Bus {
+    /// Whether the connection was passed in by the app, which the plugin
+    /// must then not close.
+    fn is_shared(&self) -> bool {
+        matches!(self, Bus::Connection(_))
+    }
+
+    fn connect(&self) -> zbus::Result<Connection> {
+        match self {
+            Bus::System => Connection::system(),
+            Bus::Connection(conn) => Ok(conn.clone()),
+            Bus::Address(address) => {
+                zbus::blocking::connection::Builder::address(address.as_str())?.build()
+            }
+        }
+    }
+}
+
+/// Where the plugin reads `LockedHint` from. Defaults to the freedesktop
+/// logind names; forks such as elogind may expose the same session interface
+/// under other names.
+#[derive(Clone)]
+pub(crate) struct Logind {
+    pub(crate) bus: Bus,
+    pub(crate) service: String,
+    pub(crate) interface: String,
+    /// Follows every seat session instead of the one of this process, see
+    /// `Builder::all_sessions`.
+    pub(crate) all_sessions: bool,
+    /// Object path of the session, looked up once and shared by all clones.
+    session_path: Arc<OnceLock<String>>,
+}
+
+impl Default for Logind {
+    fn default() -> Self {
+        Self {
+            bus: Bus::default(),
+            service: "org.freedesktop.login1".into(),
+            interface: "org.freedesktop.login1.Session".into(),
+            all_sessions: false,
+            session_path: Arc::default(),
+        }
+    }
+}
+
+impl Logind {
+    fn session<'a>(&'a self, conn: &Connection) -> zbus::Result<Proxy<'a>> {
+        Proxy::new(
+            conn,
+            self.service.as_str(),
+            self.session_path(conn),
+            self.interface.as_str(),
+        )
+    }
+
+    fn session_path(&self, conn: &Connection) -> &str {
+        self.session_path
+            .get_or_init(|| self.resolve_session_path(conn))
+    }
+
+    /// Looks up the session of this process with `GetSessionByPID`, then the
+    /// one named by `XDG_SESSION_ID`, and falls back to logind's `auto`
+    /// alias if neither resolves.
+    fn resolve_session_path(&self, conn: &Connection) -> String {
+        let manager = match Proxy::new(conn, self.service.as_str(), MANAGER_PATH, MANAGER_INTERFACE)
+        {
+            Ok(manager) => manager,
+            Err(e) => {
+                warn!("Failed to create logind manager proxy: {}", e);
+                return AUTO_SESSION_PATH.into();
+            }
+        };
+        let by_pid = manager
+            .call::<_, _, OwnedObjectPath>("GetSessionByPID", &(std::process::id(),))
+            .map_err(|e| info!("No logind session for this process: {}", e));
+        let path = by_pid.ok().or_else(|| {
+            let id = std::env::var("XDG_SESSION_ID").ok()?;
+            manager
+                .call::<_, _, OwnedObjectPath>("GetSession", &(id.as_str(),))
+                .map_err(|e| info!("No logind session {:?}: {}", id, e))
+                .ok()
+        });
+        match path {
+            Some(path) => {
+                info!("Using logind session {}", path.as_str());
+                path.as_str().to_owned()
+            }
+            None => {
+                warn!(
+                    "Failed to resolve the logind session, using {}",
+                    AUTO_SESSION_PATH
+                );
+                AUTO_SESSION_PATH.into()
+            }
+        }
+    }
+}
+
+/// Lets the `set_active_backend` command stop the running backend so the
+/// monitor can start another one in its place.
+#[derive(Default)]
+pub(crate) struct Switch {
+    requested: Mutex<Option<BackendKind>>,
+    /// Bumped on every switch, so the subscriptions of the replaced backend
+    /// stop at their next signal.
+    generation: AtomicU64,
+    /// Connections the plugin opened for the running backend. Closing them
+    /// ends its subscriptions right away.
+    connections: Mutex<Vec<Connection>>,
+    /// Connection of the running logind subscription, which `query_locked`
+    /// reads `LockedHint` over rather than connecting for every call.
+    logind: Mutex<Option<Connection>>,
+    /// Set by `stop`, after which connections are closed as soon as they are
+    /// added.
+    shut_down: AtomicBool,
+}
+
+impl Switch {
+    fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Relaxed)
+    }
+
+    fn add_connection(&self, conn: Connection) {
+        let mut connections = lock(&self.connections);
+        if self.shut_down.load(Ordering::SeqCst) {
+            let _ = conn.close();
+            return;
+        }
+        connections.push(conn);
+    }
+
+    fn set_logind_connection(&self, conn: &Connection) {
+        if !self.is_shut_down() {
+            *lock(&self.logind) = Some(conn.clone());
+        }
+    }
+
+    #[cfg(feature = "commands")]
+    fn logind_connection(&self) -> Option<Connection> {
+        lock(&self.logind).clone()
+    }
+
+    fn is_shut_down(&self) -> bool {
+        self.shut_down.load(Ordering::SeqCst)
+    }
+
+    /// Whether the running backend should end for good: the plugin stopped
+    /// or another backend was requested.
+    fn is_ending(&self) -> bool {
+        self.is_shut_down() || lock(&self.requested).is_some()
+    }
+
+    /// Ends all subscriptions of the running backend.
+    fn stop(&self) -> zbus::Result<()> {
+        self.generation.fetch_add(1, Ordering::Relaxed);
+        lock(&self.logind).take();
+        let connections = std::mem::take(&mut *lock(&self.connections));
+        for conn in connections {
+            conn.close()?;
+        }
+        Ok(())
+    }
+}
+
+/// Asks the monitor to replace the running backend with `kind`, which must be
+/// one of the available backends.
+///
+/// The running backend is stopped by closing its connections. A connection
+/// passed to `Builder::dbus_connection` isn't closed, so switching away from
+/// logind then takes effect at its next signal.
+#[cfg(feature = "commands")]
+pub(crate) fn switch_backend<R: Runtime>(app: &AppHandle<R>, kind: BackendKind) -> Result<()> {
+    if app.state::<SharedState>().active_backend().is_none() {
+        return Err(ScreenLockError::NotReady);
+    }
+    let switch = app.state::<Switch>();
+    *lock(&switch.requested) = Some(kind);
+    switch.stop()?;
+    Ok(())
+}
+
+/// Stops the monitor for good by closing its connections, and keeps backends
+/// it starts afterwards from subscribing. As with a backend switch, a
+/// connection passed to `Builder::dbus_connection` isn't closed, so a logind
+/// subscription on it ends at its next signal.
+pub(crate) fn stop<R: Runtime>(app: &AppHandle<R>) {
+    let switch = app.state::<Switch>();
+    switch.shut_down.store(true, Ordering::SeqCst);
+    lock(&switch.requested).take();
+    if let Err(e) = switch.stop() {
+        warn!("Failed to close D-Bus connection: {}", e);
+    }
+}
+
+/// Where `query_locked` reads the lock state from.
+#[cfg(feature = "commands")]
+enum LockSource {
+    Logind,
+    Screensaver(&'static ScreenSaverService),
+    Unavailable,
+}
+
+/// Picks the source the running backend reads locks from, so a query
+/// agrees with the events: logind's `LockedHint` for logind and for GNOME's
+/// screensaver in logind mode, `GetActive` for the other screensavers. While
+/// no backend is running, e.g. between a switch and the next backend
+/// subscribing, logind is used if it's available.
+#[cfg(feature = "commands")]
+fn lock_source(active: Option<BackendKind>, available: &[BackendKind]) -> LockSource {
+    let logind = available.contains(&BackendKind::Logind);
+    match active {
+        None | Some(BackendKind::Logind) if logind => LockSource::Logind,
+        Some(kind) if gnome_locks_from_logind(kind, available) => LockSource::Logind,
+        Some(kind) => SCREENSAVERS
+            .iter()
+            .find(|service| service.kind == kind)
+            .map_or(LockSource::Unavailable, LockSource::Screensaver),
+        None => LockSource::Unavailable,
+    }
+}
+
+/// Whether the screensaver backend `kind` reads lock transitions from
+/// logind, with `ActiveChanged` only reporting the screensaver: GNOME's,
+/// while logind is available.
+fn gnome_locks_from_logind(kind: BackendKind, available: &[BackendKind]) -> bool {
+    kind == BackendKind::GnomeScreenSaver && available.contains(&BackendKind::Logind)
+}
+
+/// Reads the lock state from the source of the running backend, see
+/// [`lock_source`].
+#[cfg(feature = "commands")]
+pub(crate) fn query_locked<R: Runtime>(app: &AppHandle<R>) -> Result<bool> {
+    let state = app.state::<SharedState>();
+    match lock_source(state.active_backend(), &state.available_backends()) {
+        LockSource::Logind => {}
+        LockSource::Screensaver(service) => {
+            let conn = Connection::session()?;
+            let proxy = Proxy::new(&conn, service.destination, service.path, service.interface)?;
+            return Ok(proxy.call::<_, _, bool>("GetActive", &())?);
+        }
+        LockSource::Unavailable => return Err(ScreenLockError::BackendUnavailable),
+    }
+    let logind = app.state::<Logind>();
+    let conn = match app.state::<Switch>().logind_connection() {
+        Some(conn) => conn,
+        None => logind.bus.connect()?,
+    };
+    Ok(read_locked_hint(&conn, &logind)?)
+}
+
+/// A session-bus screensaver exposing `GetActive` and the `ActiveChanged` signal.
+struct ScreenSaverService {
+    kind: BackendKind,
+    destination: &'static str,
+    path: &'static str,
+    interface: &'static str,
+}
+
+/// Screensaver fallbacks, tried in order when logind is unavailable.
+const SCREENSAVERS: [ScreenSaverService; 3] = [
+    ScreenSaverService {
+        kind: BackendKind::GnomeScreenSaver,
+        destination: "org.gnome.ScreenSaver",
+        path: "/org/gnome/ScreenSaver",
+        interface: "org.gnome.ScreenSaver",
+    },
+    ScreenSaverService {
+        kind: BackendKind::KdeScreenSaver,
+        destination: "org.kde.screensaver",
+        path: "/ScreenSaver",
+        interface: "org.freedesktop.ScreenSaver",
+    },
+    ScreenSaverService {
+        kind: BackendKind::FreedesktopScreenSaver,
+        destination: "org.freedesktop.ScreenSaver",
+        path: "/org/freedesktop/ScreenSaver",
+        interface: "org.freedesktop.ScreenSaver",
+    },
+];
+
+pub(crate) fn monitor<R: Runtime>(app: &AppHandle<R>, logind: &Logind) {
+    info!("Starting new thread for Linux screen lock monitoring...");
+    let state = app.state::<SharedState>();
+    let switch = app.state::<Switch>();
+    let mut selected = state.select_backend(available_backends(logind));
+    if selected.is_none() {
+        dispatch::backend_error(app, None, ScreenLockError::BackendUnavailable, true);
+    }
+    let mut resync_state = false;
+    while let Some(kind) = selected {
+        let fallback = state
+            .available_backends()
+            .into_iter()
+            .find(|other| *other != kind);
+        let can_fall_back = fallback.is_some();
+        let failed = match kind {
+            BackendKind::Logind => run_with_retry(
+                &switch,
+                resync_state,
+                can_fall_back,
+                supervisor::backoff,
+                |resync_state| monitor_logind(app, logind, resync_state, true),
+            ),
+            kind => match SCREENSAVERS.iter().find(|service| service.kind == kind) {
+                Some(service) => run_with_retry(
+                    &switch,
+                    resync_state,
+                    can_fall_back,
+                    supervisor::backoff,
+                    |resync_state| monitor_screensaver(app, logind, service, resync_state),
+                ),
+                None => false,
+            },
+        };
+        // Also stops the logind subscription backing the GNOME screensaver.
+        if let Err(e) = switch.stop() {
+            warn!("Failed to close D-Bus connection: {}", e);
+        }
+        selected = lock(&switch.requested).take();
+        if failed {
+            dispatch::backend_error(
+                app,
+                Some(kind),
+                ScreenLockError::DbusError(format!(
+                    "Backend {:?} keeps failing, falling back to {:?}",
+                    kind, fallback
+                )),
+                false,
+            );
+            drop_backend(&state, kind);
+            selected = fallback;
+        } else if let Some(kind) = selected {
+            info!("Switching to backend {:?}", kind);
+        }
+        if selected.is_some() {
+            state.set_active_backend(selected);
+        }
+        resync_state = true;
+    }
+    state.set_active_backend(None);
+    state.set_ready(false);
+}
+
+/// Runs `backend` until the plugin stops or another backend is requested.
+///
+/// When the backend ends by itself, e.g. because D-Bus restarted or the
+/// connection dropped over suspend, it is started again after a backoff and
+/// resyncs the state. `backend` returns whether it got as far as
+/// subscribing. With `can_fall_back`, a backend that fails to subscribe
+/// [`MAX_FAILURES`] times in a row is given up on, and `true` returned so
+/// the monitor moves on to the next one. `backoff` gives the delay before
+/// each retry from the failures so far.
+fn run_with_retry(
+    switch: &Switch,
+    mut resync_state: bool,
+    can_fall_back: bool,
+    backoff: fn(u32) -> Duration,
+    mut backend: impl FnMut(bool) -> bool,
+) -> bool {
+    let mut failures = 0;
+    loop {
+        if backend(resync_state) {
+            failures = 0;
+        } else {
+            failures += 1;
+        }
+        if switch.is_ending() {
+            return false;
+        }
+        // Also ends the logind subscription backing the GNOME screensaver,
+        // which the screensaver backend starts again.
+        if let Err(e) = switch.stop() {
+            warn!("Failed to close D-Bus connection: {}", e);
+        }
+        if can_fall_back && failures >= MAX_FAILURES {
+            return true;
+        }
+        let delay = backoff(failures);
+        warn!("D-Bus backend ended, retrying in {:?}", delay);
+        thread::sleep(delay);
+        if switch.is_ending() {
+            return false;
+        }
+        resync_state = true;
+    }
+}
+
+/// Removes a failing backend from the available ones. Without logind, the
+/// GNOME screensaver reads lock transitions from `ActiveChanged` itself.
+fn drop_backend(state: &SharedState, kind: BackendKind) {
+    let mut available = state.available_backends();
+    available.retain(|other| *other != kind);
+    state.set_available_backends(available);
+}
+
+/// Probes logind and each screensaver on the session bus.
+fn available_backends(logind: &Logind) -> Vec<BackendKind> {
+    let mut available = Vec::new();
+
+    match logind
+        .bus
+        .connect()
+        .and_then(|conn| read_locked_hint(&conn, logind))
+    {
+        Ok(_) => available.push(BackendKind::Logind),
+        Err(e) => info!("logind is not available: {}", e),
+    }
+
+    match Connection::session() {
+        Ok(conn) => {
+            for service in SCREENSAVERS.iter() {
+                let active =
+                    Proxy::new(&conn, service.destination, service.path, service.interface)
+                        .and_then(|proxy| proxy.call::<_, _, bool>("GetActive", &()));
+                if active.is_ok() {
+                    available.push(service.kind);
+                }
+            }
+        }
+        Err(e) => warn!("Failed to establish session connection: {}", e),
+    }
+    available
+}
+
+/// Reads `LockedHint` over `conn` with a plain `Get`, which spares the
+/// property fetch of a new session proxy.
+fn read_locked_hint(conn: &Connection, logind: &Logind) -> zbus::Result<bool> {
+    if logind.all_sessions {
+        return Ok(all_locked(&list_sessions(conn, logind)?));
+    }
+    session_property(conn, logind, logind.session_path(conn), "LockedHint")
+}
+
+/// State of a user session on a seat, followed with `Builder::all_sessions`.
+#[derive(Clone, Copy, PartialEq)]
+struct SeatSession {
+    active: bool,
+    locked: bool,
+}
+
+/// Whether no seat has an unlocked session in the foreground. Without any
+/// user session, e.g. on the login screen, this counts as locked.
+fn all_locked(sessions: &HashMap<String, SeatSession>) -> bool {
+    !sessions
+        .values()
+        .any(|session| session.active && !session.locked)
+}
+
+/// Reads the user sessions on a seat, keyed by object path. Sessions
+/// without a seat, e.g. over SSH, have no screen to lock, and greeter or
+/// lock-screen sessions aren't used by anyone.
+fn list_sessions(conn: &Connection, logind: &Logind) -> zbus::Result<HashMap<String, SeatSession>> {
+    let listed: Vec<(String, u32, String, String, OwnedObjectPath)> = conn
+        .call_method(
+            Some(logind.service.as_str()),
+            MANAGER_PATH,
+            Some(MANAGER_INTERFACE),
+            "ListSessions",
+            &(),
+        )?
+        .body()
+        .deserialize()?;
+    let mut sessions = HashMap::new();
+    for (_, _, _, seat, path) in listed {
+        if seat.is_empty() {
+            continue;
+        }
+        match read_seat_session(conn, logind, path.as_str()) {
+            Ok(Some(session)) => {
+                sessions.insert(path.as_str().to_owned(), session);
+            }
+            Ok(None) => {}
+            // The session may have closed in the meantime.
+            Err(e) => info!("Skipping logind session {}: {}", path.as_str(), e),
+        }
+    }
+    Ok(sessions)
+}
+
+/// Reads the session at `path`, `None` if it isn't a user session. Newer
+/// logind releases also have `user-early` and `user-incomplete` classes.
+fn read_seat_session(
+    conn: &Connection,
+    logind: &Logind,
+    path: &str,
+) -> zbus::Result<Option<SeatSession>> {
+    if !session_property::<String>(conn, logind, path, "Class")?.starts_with("user") {
+        return Ok(None);
+    }
+    Ok(Some(SeatSession {
+        active: session_property(conn, logind, path, "Active")?,
+        locked: session_property(conn, logind, path, "LockedHint")?,
+    }))
+}
+
+/// Reads a property of the session at `path`. Called directly rather than
+/// through a [`Proxy`], whose property cache may not have seen the change
+/// that prompted the read yet.
+fn session_property<T>(
+    conn: &Connection,
+    logind: &Logind,
+    path: &str,
+    name: &str,
+) -> zbus::Result<T>
+where
+    T: TryFrom<OwnedValue, Error = zbus::zvariant::Error>,
+{
+    let value: OwnedValue = conn
+        .call_method(
+            Some(logind.service.as_str()),
+            path,
+            Some(PROPERTIES_INTERFACE),
+            "Get",
+            &(logind.interface.as_str(), name),
+        )?
+        .body()
+        .deserialize()?;
+    Ok(T::try_from(value)?)
+}
+
+/// Reports the state read from a backend the monitor just switched to, if
+/// it differs from what the previous backend reported.
+fn resync<R: Runtime>(app: &AppHandle<R>, locked: bool) {
+    let session = app.state::<SharedState>().session();
+    if session.locked.is_some_and(|previous| previous != locked) {
+        dispatch::status_changed(app, locked, None, Instant::now());
+    }
+}
+
+/// Reports the `LockedHint` returned by `read` as the initial state, so a
+/// session that is already locked is reported as such rather than only from
+/// its next change, or with `resync_state` as the state to resync to. With
+/// `announce`, `monitoring_started` is emitted once the state is recorded, so
+/// `await_ready` never returns before it. Returns whether the read succeeded.
+fn report_locked_hint<R: Runtime>(
+    app: &AppHandle<R>,
+    resync_state: bool,
+    announce: bool,
+    read: impl FnOnce() -> zbus::Result<bool>,
+) -> bool {
+    let started_at = Instant::now();
+    let locked = match read() {
+        Ok(locked) => locked,
+        Err(e) => {
+            dbus_error(app, BackendKind::Logind, "Failed to read LockedHint", e);
+            return false;
+        }
+    };
+    if resync_state {
+        resync(app, locked);
+    } else {
+        dispatch::initial_status(app, BackendKind::Logind, locked, None, started_at);
+    }
+    if announce {
+        dispatch::monitoring_started(app, BackendKind::Logind);
+    }
+    true
+}
+
+/// Reports `LockedHint` changes as lock transitions. With `announce`, logind
+/// is the running backend and `monitoring_started` is emitted once
+/// subscribed; otherwise it only backs the lock state of another backend.
+/// Returns whether it subscribed.
+fn monitor_logind<R: Runtime>(
+    app: &AppHandle<R>,
+    logind: &Logind,
+    resync_state: bool,
+    announce: bool,
+) -> bool {
+    if logind.all_sessions {
+        return monitor_all_sessions(app, logind, resync_state, announce);
+    }
+    let switch = app.state::<Switch>();
+    let generation = switch.generation();
+    if switch.is_shut_down() {
+        return false;
+    }
+    let conn = match logind.bus.connect() {
+        Ok(conn) => conn,
+        Err(e) => {
+            dbus_error(
+                app,
+                BackendKind::Logind,
+                "Failed to establish D-Bus connection",
+                e,
+            );
+            return false;
+        }
+    };
+    if !logind.bus.is_shared() {
+        switch.add_connection(conn.clone());
+    }
+    switch.set_logind_connection(&conn);
+
+    let proxy = match logind.session(&conn) {
+        Ok(proxy) => proxy,
+        Err(e) => {
+            dbus_error(
+                app,
+                BackendKind::Logind,
+                "Failed to create session proxy",
+                e,
+            );
+            return false;
+        }
+    };
+
+    // The connection, proxy and signal stream used to be rebuilt after every
+    // change. Reusing them leaves decoding the property value as the only
+    // per-change work.
+    let changes = proxy.receive_property_changed::<bool>("LockedHint");
+    // Read once subscribed. Some sessions, e.g. without a seat, have no
+    // usable `LockedHint`. That counts as a failure, so the monitor falls
+    // back to a screensaver.
+    if !report_locked_hint(app, resync_state, announce, || {
+        proxy.get_property::<bool>("LockedHint")
+    }) {
+        return false;
+    }
+    // Blocks on the signal stream: nothing is polled, and a change costs one decoded bool.
+    for pro in changes {
+        if switch.generation() != generation {
+            return true;
+        }
+        let detected_at = Instant::now();
+        let current_property = match pro.get() {
+            Ok(prop) => prop,
+            Err(e) => {
+                warn!("Failed to get property: {}", e);
+                continue;
+            }
+        };
+
+        dispatch::raw_event(app, BackendKind::Logind, RawValue::Flag(current_property));
+        dispatch::raw_read(app, BackendKind::Logind, current_property, detected_at);
+        dispatch::status_changed(app, current_property, None, detected_at);
+    }
+    if switch.generation() == generation {
+        dispatch::backend_error(
+            app,
+            Some(BackendKind::Logind),
+            ScreenLockError::DbusError("No more LockedHint changes received".into()),
+            false,
+        );
+    }
+    true
+}
+
+/// Like [`monitor_logind`], for every user session on a seat: the session is
+/// reported unlocked while any seat has an unlocked session in the
+/// foreground. Sessions are added and removed as logind announces them.
+fn monitor_all_sessions<R: Runtime>(
+    app: &AppHandle<R>,
+    logind: &Logind,
+    resync_state: bool,
+    announce: bool,
+) -> bool {
+    let switch = app.state::<Switch>();
+    let generation = switch.generation();
+    if switch.is_shut_down() {
+        return false;
+    }
+    let conn = match logind.bus.connect() {
+        Ok(conn) => conn,
+        Err(e) => {
+            dbus_error(
+                app,
+                BackendKind::Logind,
+                "Failed to establish D-Bus connection",
+                e,
+            );
+            return false;
+        }
+    };
+    if !logind.bus.is_shared() {
+        switch.add_connection(conn.clone());
+    }
+    switch.set_logind_connection(&conn);
+
+    // One subscription covers the manager's `SessionNew`/`SessionRemoved`
+    // and the `PropertiesChanged` of every session object below it.
+    let messages = MatchRule::builder()
+        .msg_type(MessageType::Signal)
+        .sender(logind.service.as_str())
+        .and_then(|rule| rule.path_namespace(MANAGER_PATH))
+        .and_then(|rule| MessageIterator::for_match_rule(rule.build(), &conn, None));
+    let messages = match messages {
+        Ok(messages) => messages,
+        Err(e) => {
+            dbus_error(
+                app,
+                BackendKind::Logind,
+                "Failed to subscribe to logind sessions",
+                e,
+            );
+            return false;
+        }
+    };
+    // Read once subscribed, so a change in between isn't lost.
+    let started_at = Instant::now();
+    let mut sessions = match list_sessions(&conn, logind) {
+        Ok(sessions) => sessions,
+        Err(e) => {
+            dbus_error(
+                app,
+                BackendKind::Logind,
+                "Failed to list logind sessions",
+                e,
+            );
+            return false;
+        }
+    };
+    let locked = all_locked(&sessions);
+    if resync_state {
+        resync(app, locked);
+    } else {
+        dispatch::initial_status(app, BackendKind::Logind, locked, None, started_at);
+    }
+    if announce {
+        dispatch::monitoring_started(app, BackendKind::Logind);
+    }
+    // Blocks on logind's signals; only a change of a seat session reads its properties again.
+    for message in messages {
+        if switch.generation() != generation {
+            return true;
+        }
+        let detected_at = Instant::now();
+        let message = match message {
+            Ok(message) => message,
+            Err(e) => {
+                warn!("Failed to receive logind signal: {}", e);
+                continue;
+            }
+        };
+        let header = message.header();
+        let Some(path) = header.path().map(|path| path.as_str().to_owned()) else {
+            continue;
+        };
+        let previous = sessions.clone();
+        match header.member().map(|member| member.as_str()) {
+            // The new session's seat and class aren't in the signal.
+            Some("SessionNew") => match list_sessions(&conn, logind) {
+                Ok(listed) => sessions = listed,
+                Err(e) => warn!("Failed to list logind sessions: {}", e),
+            },
+            Some("SessionRemoved") => {
+                match message.body().deserialize::<(String, OwnedObjectPath)>() {
+                    Ok((_, removed)) => {
+                        sessions.remove(removed.as_str());
+                    }
+                    Err(e) => warn!("Failed to read SessionRemoved signal: {}", e),
+                }
+            }
+            Some("PropertiesChanged") if sessions.contains_key(&path) => {
+                type Changed = (String, HashMap<String, OwnedValue>, Vec<String>);
+                let relevant = |name: &str| name == "Active" || name == "LockedHint";
+                match message.body().deserialize::<Changed>() {
+                    Ok((interface, changed, invalidated))
+                        if interface == logind.interface
+                            && (changed.keys().any(|name| relevant(name))
+                                || invalidated.iter().any(|name| relevant(name))) =>
+                    {
+                        match read_seat_session(&conn, logind, &path) {
+                            Ok(Some(session)) => {
+                                sessions.insert(path, session);
+                            }
+                            Ok(None) => {
+                                sessions.remove(&path);
+                            }
+                            Err(e) => warn!("Failed to read logind session {}: {}", path, e),
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(e) => warn!("Failed to read PropertiesChanged signal: {}", e),
+                }
+            }
+            _ => {}
+        }
+        if sessions == previous {
+            continue;
+        }
+        let locked = all_locked(&sessions);
+        dispatch::raw_event(app, BackendKind::Logind, RawValue::Flag(locked));
+        dispatch::raw_read(app, BackendKind::Logind, locked, detected_at);
+        dispatch::status_changed(app, locked, None, detected_at);
+    }
+    if switch.generation() == generation {
+        dispatch::backend_error(
+            app,
+            Some(BackendKind::Logind),
+            ScreenLockError::DbusError("No more logind session signals received".into()),
+            false,
+        );
+    }
+    true
+}
+
+/// Follows `ActiveChanged` of `service`.
+///
+/// GNOME turns the screensaver on before the session actually locks, and
+/// logind's `LockedHint` tells the two apart. So on GNOME, when logind is
+/// available too, lock transitions are read from `LockedHint` and
+/// `ActiveChanged` only reports the screensaver. Other desktops are followed
+/// through `ActiveChanged` alone, which then reports both.
+///
+/// Returns whether it subscribed.
+fn monitor_screensaver<R: Runtime>(
+    app: &AppHandle<R>,
+    logind: &Logind,
+    service: &ScreenSaverService,
+    resync_state: bool,
+) -> bool {
+    let state = app.state::<SharedState>();
+    let switch = app.state::<Switch>();
+    let generation = switch.generation();
+    if switch.is_shut_down() {
+        return false;
+    }
+    let conn = match Connection::session() {
+        Ok(conn) => conn,
+        Err(e) => {
+            dbus_error(
+                app,
+                service.kind,
+                "Failed to establish session connection",
+                e,
+            );
+            return false;
+        }
+    };
+    switch.add_connection(conn.clone());
+
+    let proxy = match Proxy::new(&conn, service.destination, service.path, service.interface) {
+        Ok(proxy) => proxy,
+        Err(e) => {
+            let context = format!("Failed to create {} proxy", service.destination);
+            dbus_error(app, service.kind, &context, e);
+            return false;
+        }
+    };
+
+    let signals = match proxy.receive_signal("ActiveChanged") {
+        Ok(signals) => signals,
+        Err(e) => {
+            let context = format!(
+                "Failed to subscribe to {} ActiveChanged",
+                service.destination
+            );
+            dbus_error(app, service.kind, &context, e);
+            return false;
+        }
+    };
+
+    let mut lock_from_logind = gnome_locks_from_logind(service.kind, &state.available_backends());
+    if lock_from_logind {
+        info!("Reading GNOME lock transitions from logind");
+        let handle = app.clone();
+        let logind = logind.clone();
+        // Without the thread, lock transitions are read from `ActiveChanged`.
+        lock_from_logind = supervisor::spawn_named(app, "screen-lock-logind", false, move || {
+            let switch = handle.state::<Switch>();
+            let generation = switch.generation();
+            let subscribed = monitor_logind(&handle, &logind, resync_state, false);
+            // Ended by itself, so the screensaver backend is restarted and
+            // starts this subscription again, or without it if logind
+            // couldn't even subscribe.
+            if switch.generation() == generation && !switch.is_ending() {
+                if !subscribed {
+                    warn!("logind is failing, reading GNOME lock transitions from ActiveChanged");
+                    drop_backend(&handle.state::<SharedState>(), BackendKind::Logind);
+                }
+                let _ = switch.stop();
+            }
+        });
+    }
+
+    let started_at = Instant::now();
+    match proxy.call::<_, _, bool>("GetActive", &()) {
+        Ok(active) => {
+            state.record_screensaver(active);
+            if !lock_from_logind {
+                if resync_state {
+                    resync(app, active);
+                } else {
+                    dispatch::initial_status(app, service.kind, active, None, started_at);
+                }
+            }
+        }
+        Err(e) => warn!("Failed to call {} GetActive: {}", service.destination, e),
+    }
+    // Once the initial state is recorded, so `await_ready` never returns
+    // before it.
+    dispatch::monitoring_started(app, service.kind);
+    // Blocks on the signal stream: nothing is polled, and a signal costs one decoded bool.
+    for message in signals {
+        if switch.generation() != generation {
+            return true;
+        }
+        let detected_at = Instant::now();
+        let active: bool = match message.body().deserialize() {
+            Ok(active) => active,
+            Err(e) => {
+                warn!("Failed to read ActiveChanged signal: {}", e);
+                continue;
+            }
+        };
+
+        dispatch::raw_event(app, service.kind, RawValue::Flag(active));
+        let screensaver_changed = state.record_screensaver(active);
+        let mut lock_changed = false;
+        if !lock_from_logind {
+            dispatch::raw_read(app, service.kind, active, detected_at);
+            lock_changed = dispatch::status_changed(app, active, None, detected_at);
+        }
+        dispatch::screensaver_changed(app, active);
+        // The lock event already carries the new screensaver state.
+        if screensaver_changed && !lock_changed {
+            dispatch::detail_changed(app, detected_at);
+        }
+    }
+    if switch.generation() == generation {
+        dispatch::backend_error(
+            app,
+            Some(service.kind),
+            ScreenLockError::DbusError(format!(
+                "No more ActiveChanged signals received from {}",
+                service.destination
+            )),
+            false,
+        );
+    }
+    true
+}
+
+/// Reports a failed D-Bus step of `backend`, which is then retried.
+fn dbus_error<R: Runtime>(
+    app: &AppHandle<R>,
+    backend: BackendKind,
+    context: &str,
+    error: zbus::Error,
+) {
+    dispatch::backend_error(
+        app,
+        Some(backend),
+        ScreenLockError::DbusError(format!("{}: {}", context, error)),
+        false,
+    );
+}
+
+/// Idle time sources on the session bus, tried in order: GNOME's Mutter,
+/// then KDE's screensaver. Each is a destination, path, interface, method
+/// and whether the method returns a `u64`, as Mutter does, or a `u32`.
+const IDLE_SOURCES: [(&str, &str, &str, &str, bool); 2] = [
+    (
+        "org.gnome.Mutter.IdleMonitor",
+        "/org/gnome/Mutter/IdleMonitor/Core",
+        "org.gnome.Mutter.IdleMonitor",
+        "GetIdletime",
+        true,
+    ),
+    (
+        "org.freedesktop.ScreenSaver",
+        "/org/freedesktop/ScreenSaver",
+        "org.freedesktop.ScreenSaver",
+        "GetSessionIdleTime",
+        false,
+    ),
+];
+
+/// Returns a reader of the time since the last user input, for presence.
+/// It connects to the session bus on its first call and keeps the
+/// connection; a failed read returns `None` and is only logged once.
+pub(crate) fn idle_time_reader() -> impl FnMut() -> Option<Duration> {
+    let mut conn: Option<Connection> = None;
+    let mut warned = false;
+    move || {
+        if conn.is_none() {
+            conn = Connection::session().ok();
+        }
+        let read = conn.as_ref().and_then(|conn| {
+            IDLE_SOURCES
+                .iter()
+                .find_map(|&(destination, path, interface, method, wide)| {
+                    let reply = conn
+                        .call_method(Some(destination), path, Some(interface), method, &())
+                        .ok()?;
+                    let body = reply.body();
+                    let ms = if wide {
+                        body.deserialize::<u64>().ok()?
+                    } else {
+                        u64::from(body.deserialize::<u32>().ok()?)
+                    };
+                    Some(Duration::from_millis(ms))
+                })
+        });
+        if read.is_none() && !warned {
+            warned = true;
+            warn!("No idle time source on the session bus, presence is unknown");
+        }
+        read
+    }
+}
+
+/// Infers whether unlocking needs a password from GNOME's `lock-enabled`
+/// setting. Other desktops don't expose a comparable setting, so this is
+/// `None` there.
+pub(crate) fn requires_password() -> Option<bool> {
+    let output = Command::new("gsettings")
+        .args(["get", "org.gnome.desktop.screensaver", "lock-enabled"])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    match String::from_utf8_lossy(&output.stdout).trim() {
+        "true" => Some(true),
+        "false" => Some(false),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events;
+    use tauri::test::{mock_builder, mock_context, noop_assets, MockRuntime};
+    use tauri::{App, Listener};
+
+    /// An app running the plugin without starting a backend, with the
+    /// payloads of the lock and unlock events it emits.
+    fn app() -> (App<MockRuntime>, Arc<Mutex<Vec<String>>>) {
+        let app = mock_builder()
+            .plugin(crate::Builder::new().auto_start(false).build())
+            .build(mock_context(noop_assets()))
+            .unwrap();
+        let emitted = Arc::new(Mutex::new(Vec::new()));
+        let recorded = emitted.clone();
+        app.listen(events::CHANGE_SESSION_STATUS, move |event| {
+            recorded.lock().unwrap().push(event.payload().to_owned());
+        });
+        (app, emitted)
+    }
+
+    #[test]
+    fn locked_session_is_reported_locked_first() {
+        let (app, emitted) = app();
+        assert!(report_locked_hint(app.handle(), false, true, || Ok(true)));
+        let emitted = emitted.lock().unwrap();
+        assert!(
+            emitted
+                .first()
+                .is_some_and(|payload| payload.contains(r#""status":"locked""#)),
+            "{emitted:?}"
+        );
+    }
+
+    #[test]
+    fn initial_state_is_recorded_when_ready() {
+        let (app, _) = app();
+        let seen = Arc::new(Mutex::new(None));
+        let recorded = seen.clone();
+        let handle = app.handle().clone();
+        app.listen(events::MONITORING_STARTED, move |_| {
+            let state = handle.state::<SharedState>();
+            *recorded.lock().unwrap() = Some(state.session().locked);
+        });
+        assert!(report_locked_hint(app.handle(), false, true, || Ok(true)));
+        assert_eq!(*seen.lock().unwrap(), Some(Some(true)));
+        assert_eq!(app.state::<SharedState>().wait_ready(None), Some(true));
+    }
+
+    #[test]
+    fn failed_locked_hint_read_reports_nothing() {
+        let (app, emitted) = app();
+        let read = || Err(zbus::Error::Failure("no LockedHint".into()));
+        assert!(!report_locked_hint(app.handle(), false, true, read));
+        assert!(emitted.lock().unwrap().is_empty());
+    }
+
+    fn no_backoff(_: u32) -> Duration {
+        Duration::ZERO
+    }
+
+    #[test]
+    fn retry_resets_failures_once_subscribed() {
+        let switch = Switch::default();
+        // One failure short of falling back on both sides of a subscription,
+        // so only a reset count keeps the backend from being given up on.
+        let short = vec![false; MAX_FAILURES as usize - 1];
+        let mut results = short
+            .iter()
+            .chain(std::iter::once(&true))
+            .chain(short.iter());
+        let mut resyncs = Vec::new();
+        let fell_back = run_with_retry(&switch, false, true, no_backoff, |resync_state| {
+            resyncs.push(resync_state);
+            match results.next() {
+                Some(subscribed) => *subscribed,
+                None => {
+                    *lock(&switch.requested) = Some(BackendKind::GnomeScreenSaver);
+                    false
+                }
+            }
+        });
+        assert!(!fell_back);
+        assert_eq!(resyncs.len(), 2 * MAX_FAILURES as usize);
+        // Every retry resyncs the state.
+        assert!(!resyncs[0]);
+        assert!(resyncs[1..].iter().all(|resync_state| *resync_state));
+    }
+
+    #[test]
+    fn retry_falls_back_after_max_failures() {
+        let switch = Switch::default();
+        let mut attempts = 0;
+        let fell_back = run_with_retry(&switch, false, true, no_backoff, |_| {
+            attempts += 1;
+            false
+        });
+        assert!(fell_back);
+        assert_eq!(attempts, MAX_FAILURES);
+    }
+
+    #[test]
+    fn retry_keeps_going_without_fallback() {
+        let switch = Switch::default();
+        let mut attempts = 0;
+        let fell_back = run_with_retry(&switch, false, false, no_backoff, |_| {
+            attempts += 1;
+            if attempts > MAX_FAILURES {
+                *lock(&switch.requested) = Some(BackendKind::Logind);
+            }
+            false
+        });
+        assert!(!fell_back);
+        assert_eq!(attempts, MAX_FAILURES + 1);
+    }
+
+    #[cfg(feature = "commands")]
+    #[test]
+    fn gnome_with_logind_is_queried_through_logind() {
+        let available = [BackendKind::Logind, BackendKind::GnomeScreenSaver];
+        let source = lock_source(Some(BackendKind::GnomeScreenSaver), &available);
+        assert!(matches!(source, LockSource::Logind));
+    }
+
+    #[cfg(feature = "commands")]
+    #[test]
+    fn screensaver_without_logind_is_queried_through_get_active() {
+        let available = [BackendKind::GnomeScreenSaver, BackendKind::KdeScreenSaver];
+        let source = lock_source(Some(BackendKind::GnomeScreenSaver), &available);
+        assert!(matches!(
+            source,
+            LockSource::Screensaver(service) if service.kind == BackendKind::GnomeScreenSaver
+        ));
+        let available = [BackendKind::Logind, BackendKind::KdeScreenSaver];
+        let source = lock_source(Some(BackendKind::KdeScreenSaver), &available);
+        assert!(matches!(
+            source,
+            LockSource::Screensaver(service) if service.kind == BackendKind::KdeScreenSaver
+        ));
+    }
+}
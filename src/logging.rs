@@ -0,0 +1,30 @@
+//! `info!` and `warn!` for the plugin's diagnostics. They forward to
+//! `tracing` with the `tracing` feature and compile to nothing without it.
+
+macro_rules! log_info {
+    ($($arg:tt)*) => {{
+        #[cfg(feature = "tracing")]
+        tracing::info!($($arg)*);
+        #[cfg(not(feature = "tracing"))]
+        $crate::logging::discard(format_args!($($arg)*));
+    }};
+}
+
+macro_rules! log_warn {
+    ($($arg:tt)*) => {{
+        #[cfg(feature = "tracing")]
+        tracing::warn!($($arg)*);
+        #[cfg(not(feature = "tracing"))]
+        $crate::logging::discard(format_args!($($arg)*));
+    }};
+}
+
+// Renamed on export: a `warn` macro defined here would be ambiguous with
+// the built-in `#[warn]` attribute.
+pub(crate) use {log_info as info, log_warn as warn};
+
+/// Keeps the arguments of a disabled message used, so they still type-check
+/// without warnings.
+#[cfg(not(feature = "tracing"))]
+#[inline(always)]
+pub(crate) fn discard(_: std::fmt::Arguments) {}
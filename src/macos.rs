@@ -0,0 +1,319 @@
+extern crate core_foundation;
+extern crate core_graphics;
+
+use crate::dispatch;
+#[cfg(feature = "commands")]
+use crate::error::Result;
+use crate::error::ScreenLockError;
+use crate::logging::info;
+use crate::models::{BackendKind, LockReason};
+use crate::state::{lock, SharedState};
+use core_foundation::{
+    base::{CFType, CFTypeRef, TCFType, ToVoid},
+    boolean::CFBoolean,
+    dictionary::{CFDictionary, CFDictionaryRef},
+    number::CFNumber,
+    runloop::CFRunLoop,
+    string::{CFString, CFStringRef},
+};
+use std::ffi::c_void;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Manager, Runtime};
+
+/// Posted when the screen locks and unlocks.
+const SCREEN_IS_LOCKED: &str = "com.apple.screenIsLocked";
+const SCREEN_IS_UNLOCKED: &str = "com.apple.screenIsUnlocked";
+/// Posted before the screensaver starts, which locks the session when a
+/// password is required after it.
+const SCREENSAVER_WILL_START: &str = "com.apple.screensaver.willstart";
+/// Posted when the screensaver has started and stopped.
+const SCREENSAVER_DID_START: &str = "com.apple.screensaver.didstart";
+const SCREENSAVER_DID_STOP: &str = "com.apple.screensaver.didstop";
+/// `kCGEventSourceStateCombinedSessionState` and `kCGAnyInputEventType`.
+const COMBINED_SESSION_STATE: i32 = 0;
+const ANY_INPUT_EVENT: u32 = u32::MAX;
+/// `CFNotificationSuspensionBehaviorDeliverImmediately`.
+const DELIVER_IMMEDIATELY: isize = 4;
+
+type NotificationCallback =
+    extern "C" fn(*mut c_void, *mut c_void, CFStringRef, *const c_void, CFDictionaryRef);
+
+extern "C" {
+    fn CGSessionCopyCurrentDictionary() -> CFDictionaryRef;
+    fn CFPreferencesCopyAppValue(key: CFStringRef, application_id: CFStringRef) -> CFTypeRef;
+    fn CFNotificationCenterGetDistributedCenter() -> *mut c_void;
+    fn CFNotificationCenterAddObserver(
+        center: *mut c_void,
+        observer: *const c_void,
+        callback: NotificationCallback,
+        name: CFStringRef,
+        object: *const c_void,
+        suspension_behavior: isize,
+    );
+    fn CFNotificationCenterRemoveEveryObserver(center: *mut c_void, observer: *const c_void);
+    fn CGEventSourceSecondsSinceLastEventType(state_id: i32, event_type: u32) -> f64;
+}
+
+/// Run loops of the monitoring threads, so `stop` can stop them.
+#[derive(Default)]
+pub(crate) struct RunLoops(Mutex<Vec<CFRunLoop>>);
+
+// `CFRunLoopStop` may be called from any thread, which is all `stop` does
+// with the run loops.
+unsafe impl Send for RunLoops {}
+unsafe impl Sync for RunLoops {}
+
+/// Stops the run loops, after which the monitoring threads remove their
+/// observers and exit.
+pub(crate) fn stop<R: Runtime>(app: &AppHandle<R>) {
+    for run_loop in lock(&app.state::<RunLoops>().0).iter() {
+        run_loop.stop();
+    }
+}
+
+/// Observes `names` on the distributed center with `callback`, which gets a
+/// copy of `app` as the observer. The notifications are delivered to the run
+/// loop of the calling thread, see [`run_until_stopped`].
+fn add_observers<R: Runtime>(
+    app: &AppHandle<R>,
+    names: &[&str],
+    callback: NotificationCallback,
+) -> *mut AppHandle<R> {
+    let observer = Box::into_raw(Box::new(app.clone()));
+    for name in names {
+        let name = CFString::new(name);
+        unsafe {
+            CFNotificationCenterAddObserver(
+                CFNotificationCenterGetDistributedCenter(),
+                observer as *const c_void,
+                callback,
+                name.as_concrete_TypeRef(),
+                std::ptr::null(),
+                DELIVER_IMMEDIATELY,
+            );
+        }
+    }
+    observer
+}
+
+/// Runs this thread's run loop until `stop` is called, then removes and
+/// frees `observer`.
+fn run_until_stopped<R: Runtime>(app: &AppHandle<R>, observer: *mut AppHandle<R>) {
+    let run_loop = CFRunLoop::get_current();
+    let run_loops = app.state::<RunLoops>();
+    lock(&run_loops.0).push(run_loop.clone());
+    // Checked after publishing the run loop, so a concurrent `stop` either
+    // sees it or is seen here.
+    if !app.state::<SharedState>().is_stopped() {
+        // Sleeps until a notification: its name as a `String`, plus a dictionary read on a lock.
+        CFRunLoop::run_current();
+    }
+    lock(&run_loops.0)
+        .retain(|other| other.as_concrete_TypeRef() != run_loop.as_concrete_TypeRef());
+    unsafe {
+        CFNotificationCenterRemoveEveryObserver(
+            CFNotificationCenterGetDistributedCenter(),
+            observer as *const c_void,
+        );
+        drop(Box::from_raw(observer));
+    }
+}
+
+/// Reads a boolean-like entry (`CFBoolean` or `CFNumber`) from the session dictionary.
+fn session_flag(session_dictionary: &CFDictionary, key: &str) -> Option<bool> {
+    let value = session_dictionary.find(CFString::new(key).to_void())?;
+    cf_flag(unsafe { CFType::wrap_under_get_rule(*value) })
+}
+
+/// Reads the "require password after sleep or screen saver" user preference.
+/// Managed devices may enforce a password through a profile regardless of
+/// this value, and recent macOS releases may not expose it at all.
+pub(crate) fn requires_password() -> Option<bool> {
+    let key = CFString::new("askForPassword");
+    let application_id = CFString::new("com.apple.screensaver");
+    let value = unsafe {
+        CFPreferencesCopyAppValue(
+            key.as_concrete_TypeRef(),
+            application_id.as_concrete_TypeRef(),
+        )
+    };
+    if value.is_null() {
+        return None;
+    }
+    cf_flag(unsafe { CFType::wrap_under_create_rule(value) })
+}
+
+fn cf_flag(value: CFType) -> Option<bool> {
+    match value.downcast::<CFBoolean>() {
+        Some(flag) => Some(flag.into()),
+        None => value
+            .downcast::<CFNumber>()
+            .and_then(|number| number.to_i64())
+            .map(|number| number != 0),
+    }
+}
+
+/// Guesses whether a lock was caused remotely. This is a heuristic: the keys
+/// are undocumented and not every screen-sharing tool or MDM vendor sets them.
+fn lock_reason(session_dictionary: &CFDictionary) -> Option<LockReason> {
+    if session_flag(session_dictionary, "kCGSSessionLoginwindowSafeLogin") == Some(true) {
+        return Some(LockReason::Managed);
+    }
+    if session_flag(session_dictionary, "kCGSSessionOnConsoleKey") == Some(false) {
+        return Some(LockReason::Remote);
+    }
+    None
+}
+
+/// Copies the current session dictionary, `None` outside a GUI session,
+/// e.g. in a launchd daemon without an Aqua session. Notifications are still
+/// observed then, and report the lock state once one is posted.
+fn session_dictionary() -> Option<CFDictionary> {
+    let session_dictionary_ref = unsafe { CGSessionCopyCurrentDictionary() };
+    if session_dictionary_ref.is_null() {
+        return None;
+    }
+    Some(unsafe { CFDictionary::wrap_under_create_rule(session_dictionary_ref) })
+}
+
+fn screen_is_locked(session_dictionary: &CFDictionary) -> bool {
+    session_dictionary.contains_key(&CFString::new("CGSSessionScreenIsLocked").to_void())
+}
+
+/// Reads the lock state from the current session dictionary.
+#[cfg(feature = "commands")]
+pub(crate) fn query_locked() -> Result<bool> {
+    let session_dictionary = session_dictionary().ok_or_else(|| {
+        ScreenLockError::PlatformError("No session dictionary, not running in a GUI session".into())
+    })?;
+    Ok(screen_is_locked(&session_dictionary))
+}
+
+/// Reads the initial state from the session dictionary, then follows the
+/// `com.apple.screenIsLocked`/`com.apple.screenIsUnlocked` distributed
+/// notifications, and those of the screensaver starting and stopping, on this
+/// thread's run loop until the plugin is stopped.
+pub(crate) fn monitor<R: Runtime>(app: &AppHandle<R>) {
+    info!("Starting new thread for macOS screen lock monitoring...");
+    let state = app.state::<SharedState>();
+    state.select_backend(vec![BackendKind::CgSession]);
+
+    let observer = add_observers(
+        app,
+        &[
+            SCREEN_IS_LOCKED,
+            SCREEN_IS_UNLOCKED,
+            SCREENSAVER_DID_START,
+            SCREENSAVER_DID_STOP,
+        ],
+        session_changed::<R>,
+    );
+
+    // Subscribed first, so a change while reading the initial state isn't
+    // lost: it is delivered once the run loop runs, after the initial state.
+    let started_at = Instant::now();
+    match session_dictionary() {
+        Some(session_dictionary) => {
+            let locked = screen_is_locked(&session_dictionary);
+            let reason = record_details(app, Some(&session_dictionary), locked);
+            dispatch::initial_status(app, BackendKind::CgSession, locked, reason, started_at);
+        }
+        None => dispatch::backend_error(
+            app,
+            Some(BackendKind::CgSession),
+            ScreenLockError::PlatformError(
+                "Not running in a GUI session, the initial lock state is unknown".into(),
+            ),
+            false,
+        ),
+    }
+    dispatch::monitoring_started(app, BackendKind::CgSession);
+    run_until_stopped(app, observer);
+    state.set_active_backend(None);
+    state.set_ready(false);
+    if !state.is_stopped() {
+        dispatch::backend_error(
+            app,
+            Some(BackendKind::CgSession),
+            ScreenLockError::PlatformError("Run loop exited, no more lock notifications".into()),
+            true,
+        );
+    }
+}
+
+extern "C" fn session_changed<R: Runtime>(
+    _center: *mut c_void,
+    observer: *mut c_void,
+    name: CFStringRef,
+    _object: *const c_void,
+    _user_info: CFDictionaryRef,
+) {
+    let detected_at = Instant::now();
+    let app = unsafe { &*(observer as *const AppHandle<R>) };
+    let name = unsafe { CFString::wrap_under_get_rule(name) }.to_string();
+    let locked = match name.as_str() {
+        SCREEN_IS_LOCKED => true,
+        SCREEN_IS_UNLOCKED => false,
+        screensaver => {
+            let active = screensaver == SCREENSAVER_DID_START;
+            if app.state::<SharedState>().record_screensaver(active) {
+                dispatch::detail_changed(app, detected_at);
+            }
+            return;
+        }
+    };
+    dispatch::raw_read(app, BackendKind::CgSession, locked, detected_at);
+    let reason = record_details(app, session_dictionary().as_ref(), locked);
+    dispatch::status_changed(app, locked, reason, detected_at);
+}
+
+/// Records the remote state read from `session_dictionary` when available,
+/// and returns the reason of a lock.
+fn record_details<R: Runtime>(
+    app: &AppHandle<R>,
+    session_dictionary: Option<&CFDictionary>,
+    locked: bool,
+) -> Option<LockReason> {
+    if let Some(session_dictionary) = session_dictionary {
+        app.state::<SharedState>().record_remote(
+            session_flag(session_dictionary, "kCGSSessionOnConsoleKey")
+                .map(|on_console| !on_console),
+        );
+    }
+    if locked {
+        session_dictionary.and_then(lock_reason)
+    } else {
+        None
+    }
+}
+
+/// Emits `pre_lock` when the screensaver is about to start, unless it is
+/// known not to require a password. Runs a run loop for the distributed
+/// notifications until the plugin is stopped.
+pub(crate) fn watch_pre_lock<R: Runtime>(app: AppHandle<R>) {
+    info!("Starting new thread for macOS pre-lock detection...");
+    let observer = add_observers(&app, &[SCREENSAVER_WILL_START], screensaver_will_start::<R>);
+    run_until_stopped(&app, observer);
+}
+
+extern "C" fn screensaver_will_start<R: Runtime>(
+    _center: *mut c_void,
+    observer: *mut c_void,
+    _name: CFStringRef,
+    _object: *const c_void,
+    _user_info: CFDictionaryRef,
+) {
+    let app = unsafe { &*(observer as *const AppHandle<R>) };
+    if requires_password() == Some(false) {
+        return;
+    }
+    dispatch::pre_lock(app, None);
+}
+
+/// Time since the last keyboard, mouse or tablet input in this session.
+pub(crate) fn idle_time() -> Option<Duration> {
+    let seconds =
+        unsafe { CGEventSourceSecondsSinceLastEventType(COMBINED_SESSION_STATE, ANY_INPUT_EVENT) };
+    Duration::try_from_secs_f64(seconds).ok()
+}
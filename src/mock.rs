@@ -0,0 +1,161 @@
+use crate::dispatch;
+use crate::logging::info;
+use crate::models::{BackendKind, LockStatus};
+use crate::state::SharedState;
+use std::time::Instant;
+use tauri::{AppHandle, Manager, Runtime};
+
+/// Starts the mock backend in place of the platform ones. It has no thread
+/// of its own: transitions are driven by [`simulate`].
+pub(crate) fn monitor<R: Runtime>(app: &AppHandle<R>) {
+    info!("Using the mock screen lock backend");
+    app.state::<SharedState>()
+        .select_backend(vec![BackendKind::Mock]);
+    dispatch::monitoring_started(app, BackendKind::Mock);
+}
+
+/// Reports `status` as if the OS had detected it, through the same path as
+/// the platform backends: the unlock debounce, the lock and unlock event,
+/// `Builder::on_change`, `subscribe` and the recorded state all see it.
+///
+/// `ScreensaverActive` and `DisplaySleep` mark the session unlocked with the
+/// screensaver running or the display off, and are only reported as such
+/// with `Builder::detailed_status`. `Unlocked` clears both. Does nothing
+/// once the plugin is stopped.
+///
+/// Only available with the `mock` feature, which starts no platform backend.
+///
+/// Panics if the plugin isn't registered on `manager`.
+pub fn simulate<R: Runtime, M: Manager<R>>(manager: &M, status: LockStatus) {
+    let app = manager.app_handle();
+    let state = app.state::<SharedState>();
+    if state.is_stopped() {
+        return;
+    }
+    let detected_at = Instant::now();
+    let locked = status == LockStatus::Locked;
+    if !locked {
+        state.record_screensaver(status == LockStatus::ScreensaverActive);
+        state.record_display_power(status != LockStatus::DisplaySleep);
+    }
+    if state.session().locked == Some(locked) && !locked {
+        dispatch::detail_changed(app, detected_at);
+    } else {
+        dispatch::status_changed(app, locked, None, detected_at);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events;
+    use std::sync::{Arc, Mutex};
+    use tauri::test::{mock_builder, mock_context, noop_assets, MockRuntime};
+    use tauri::{App, Listener};
+
+    /// An app running the plugin, with the statuses passed to `on_change`.
+    fn app() -> (App<MockRuntime>, Arc<Mutex<Vec<LockStatus>>>) {
+        let changes = Arc::new(Mutex::new(Vec::new()));
+        let recorded = changes.clone();
+        let plugin = crate::Builder::new()
+            .on_change(move |status| recorded.lock().unwrap().push(status))
+            .build();
+        let app = mock_builder()
+            .plugin(plugin)
+            .build(mock_context(noop_assets()))
+            .unwrap();
+        (app, changes)
+    }
+
+    #[test]
+    fn repeated_lock_is_delivered_once() {
+        let (app, changes) = app();
+        simulate(&app, LockStatus::Unlocked);
+        simulate(&app, LockStatus::Locked);
+        simulate(&app, LockStatus::Locked);
+        assert_eq!(
+            *changes.lock().unwrap(),
+            [LockStatus::Unlocked, LockStatus::Locked]
+        );
+    }
+
+    #[cfg(feature = "commands")]
+    #[test]
+    fn repeated_lock_is_counted_once() {
+        let (app, _) = app();
+        simulate(&app, LockStatus::Unlocked);
+        simulate(&app, LockStatus::Locked);
+        simulate(&app, LockStatus::Locked);
+        let metrics = dispatch::metrics(&app.state::<SharedState>());
+        assert_eq!(metrics.lock_count, 1);
+        assert_eq!(metrics.unlock_count, 0);
+        assert_eq!(metrics.current, Some(LockStatus::Locked));
+    }
+
+    #[test]
+    fn repeated_unlock_is_delivered_once() {
+        let (app, changes) = app();
+        simulate(&app, LockStatus::Locked);
+        simulate(&app, LockStatus::Unlocked);
+        simulate(&app, LockStatus::Unlocked);
+        assert_eq!(
+            *changes.lock().unwrap(),
+            [LockStatus::Locked, LockStatus::Unlocked]
+        );
+    }
+
+    #[test]
+    fn start_after_stop_fails() {
+        let app = mock_builder()
+            .plugin(crate::Builder::new().auto_start(false).build())
+            .build(mock_context(noop_assets()))
+            .unwrap();
+        crate::stop(&app);
+        assert_eq!(crate::start(&app), Err(crate::ScreenLockError::Stopped));
+        assert!(app.state::<SharedState>().session().locked.is_none());
+    }
+
+    #[test]
+    fn simulated_events_are_supported() {
+        let app = mock_builder()
+            .plugin(
+                crate::Builder::new()
+                    .auto_start(false)
+                    .any_change_events(true)
+                    .detailed_status(true)
+                    .build(),
+            )
+            .build(mock_context(noop_assets()))
+            .unwrap();
+        let fired = Arc::new(Mutex::new(Vec::new()));
+        for channel in [
+            events::CHANGE_SESSION_STATUS,
+            events::MONITORING_STARTED,
+            events::ANY_CHANGE,
+            events::MONITOR_ERROR,
+            events::BACKEND_ERROR,
+            events::SCREENSAVER_CHANGED,
+            events::PRE_LOCK,
+            events::PRESENCE_CHANGED,
+            events::RESTORED,
+            events::RAW_EVENT,
+        ] {
+            let fired = fired.clone();
+            app.listen(channel, move |_| fired.lock().unwrap().push(channel));
+        }
+        crate::start(&app).unwrap();
+        for status in [
+            LockStatus::Locked,
+            LockStatus::Unlocked,
+            LockStatus::ScreensaverActive,
+            LockStatus::DisplaySleep,
+        ] {
+            simulate(&app, status);
+        }
+        let fired = fired.lock().unwrap();
+        assert!(fired.contains(&events::CHANGE_SESSION_STATUS));
+        assert!(fired
+            .iter()
+            .all(|channel| events::SUPPORTED_EVENTS.contains(channel)));
+    }
+}
@@ -0,0 +1,382 @@
+use crate::error::ScreenLockError;
+use serde::{Deserialize, Serialize};
+
+/// Payload of the `window_screen_lock_status://change_session_status` event.
+#[derive(Clone, Debug, Serialize)]
+pub struct SessionStatusEvent {
+    /// `"locked"` or `"unlocked"`, or with `Builder::detailed_status` also
+    /// `"screensaver_active"` or `"display_sleep"`.
+    pub status: LockStatus,
+    /// Best-effort hint about what caused the lock, omitted when unknown.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<LockReason>,
+    /// Whether unlocking needs a password, see [`SessionInfo::requires_password`].
+    /// Only set on lock events when enabled with
+    /// `Builder::annotate_requires_password`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub requires_password: Option<bool>,
+    /// Unix time in ms at which the backend detected the transition. A
+    /// debounced unlock keeps the time it was detected, not delivered.
+    /// Events replayed from `Builder::restore_from` carry the restored
+    /// `since_ms`, the time of the last transition, as earlier times aren't
+    /// persisted.
+    pub timestamp_ms: u64,
+    /// Increases by one with every event the plugin emits, on any channel,
+    /// so a gap means an event was missed. Starts over when the plugin is
+    /// initialized again and is not persisted.
+    pub seq: u64,
+}
+
+impl SessionStatusEvent {
+    pub fn lock(seq: u64, reason: Option<LockReason>, timestamp_ms: u64) -> Self {
+        Self {
+            status: LockStatus::Locked,
+            reason,
+            requires_password: None,
+            timestamp_ms,
+            seq,
+        }
+    }
+
+    pub fn unlock(seq: u64, timestamp_ms: u64) -> Self {
+        Self {
+            status: LockStatus::Unlocked,
+            reason: None,
+            requires_password: None,
+            timestamp_ms,
+            seq,
+        }
+    }
+}
+
+/// Payload of the `window_screen_lock_status://monitor_error` event, emitted
+/// when a monitoring thread panics.
+#[derive(Clone, Debug, Serialize)]
+pub struct MonitorErrorEvent {
+    pub message: String,
+    /// Times the monitor had already been restarted before this panic.
+    pub restarts: u32,
+    /// Whether the monitor is going to be restarted.
+    pub will_restart: bool,
+    pub seq: u64,
+}
+
+/// Payload of the `window_screen_lock_status://error` event, emitted when a
+/// backend fails, e.g. its D-Bus connection drops or the Windows message
+/// window can't be created.
+#[derive(Clone, Debug, Serialize)]
+pub struct BackendErrorEvent {
+    /// Serialized as `kind` and `message`, as for a failed command.
+    #[serde(flatten)]
+    pub error: ScreenLockError,
+    /// Backend that failed, if the failure is specific to one.
+    pub backend: Option<BackendKind>,
+    /// Whether lock detection stopped for good. Otherwise the plugin
+    /// retries or falls back to another backend.
+    pub fatal: bool,
+    pub seq: u64,
+}
+
+/// Payload of the `window_screen_lock_status://restored` event, emitted when
+/// the first lock state a backend reads differs from the one restored with
+/// `Builder::restore_from`. A regular lock or unlock event follows.
+#[derive(Clone, Debug, Serialize)]
+pub struct RestoredEvent {
+    /// Lock state that was restored.
+    pub persisted: bool,
+    /// Lock state read from the backend.
+    pub locked: bool,
+    pub seq: u64,
+}
+
+/// State saved through `Builder::persist_with` and loaded through
+/// `Builder::restore_from`, so it survives a restart of the plugin or app.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PersistedState {
+    /// Last delivered lock state.
+    pub locked: Option<bool>,
+    /// Unix time in ms at which `locked` took its value.
+    pub since_ms: Option<u64>,
+    /// Delivered lock states (`true` for a lock), oldest first, replayed to
+    /// newly loaded windows. Capped at `Builder::replay_on_window_create`.
+    pub history: Vec<bool>,
+}
+
+/// Payload of the `window_screen_lock_status://monitoring_started` event,
+/// emitted once the monitor has subscribed to `backend` and lock changes are
+/// being reported.
+#[derive(Clone, Debug, Serialize)]
+pub struct MonitoringStartedEvent {
+    pub backend: BackendKind,
+    pub seq: u64,
+}
+
+/// Payload of the `window_screen_lock_status://screensaver_changed` event,
+/// emitted on Linux when a screensaver backend reports `ActiveChanged` and
+/// [`Channel::Screensaver`] is enabled.
+///
+/// On GNOME with logind available, `active` and `locked` are separate states:
+/// the screensaver may be on (`active: true`) while the session isn't locked
+/// yet (`locked: false`), e.g. during the lock delay. Other desktops report
+/// a single state, so `locked` follows `active` there.
+#[derive(Clone, Debug, Serialize)]
+pub struct ScreensaverEvent {
+    pub active: bool,
+    /// Lock state at the time of the event, `None` if not known yet.
+    pub locked: Option<bool>,
+    pub seq: u64,
+}
+
+/// Payload of the `window_screen_lock_status://pre_lock` event, emitted on
+/// macOS and Windows when a lock looks imminent and [`Channel::PreLock`] is
+/// enabled.
+///
+/// This is a heuristic with false positives: the lock may never follow, e.g.
+/// when the user moves the mouse in time, and locks without warning signs,
+/// such as Win+L, come without this event.
+#[derive(Clone, Debug, Serialize)]
+pub struct PreLockEvent {
+    /// Estimated time until the lock, `None` if unknown (always on macOS).
+    pub lock_in_ms: Option<u64>,
+    pub seq: u64,
+}
+
+/// Payload of the `window_screen_lock_status://presence_changed` event,
+/// emitted when [`Channel::Presence`] is enabled and the user becomes present
+/// or away.
+///
+/// The user counts as present while the session is unlocked and the time
+/// since the last input is below the threshold set with `set_idle_threshold`.
+/// A lock makes the user away right away; coming back takes an unlock.
+#[derive(Clone, Debug, Serialize)]
+pub struct PresenceEvent {
+    pub present: bool,
+    /// Time since the last user input in ms, `None` if it couldn't be read.
+    pub idle_ms: Option<u64>,
+    pub seq: u64,
+}
+
+/// Payload of the diagnostic `window_screen_lock_status://raw_event` event,
+/// emitted for every signal a backend receives, even when the lock state
+/// didn't change. Only sent when enabled with `Builder::raw_events`.
+#[derive(Clone, Debug, Serialize)]
+pub struct RawEvent {
+    pub backend: BackendKind,
+    pub value: RawValue,
+    /// Unix time in ms at which the signal was received.
+    pub timestamp_ms: u64,
+    pub seq: u64,
+}
+
+/// Unprocessed value reported by a backend.
+#[derive(Clone, Copy, Debug, Serialize)]
+#[serde(untagged)]
+pub enum RawValue {
+    /// Linux: the `LockedHint` property or the `ActiveChanged` argument.
+    Flag(bool),
+    /// Windows: `wParam` of `WM_WTSSESSION_CHANGE`, e.g. `7` for `WTS_SESSION_LOCK`.
+    Code(u32),
+}
+
+/// Lock state of the session, serialized as `"locked"`, `"unlocked"`,
+/// `"screensaver_active"` or `"display_sleep"`.
+///
+/// The last two refine `Unlocked` and are only reported with
+/// `Builder::detailed_status`, where the platform detects them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LockStatus {
+    Locked,
+    Unlocked,
+    /// The screensaver is running, but the session isn't locked (yet).
+    /// Linux with the GNOME screensaver and logind, and macOS.
+    ScreensaverActive,
+    /// The display is off, but the session isn't locked. Windows only.
+    DisplaySleep,
+}
+
+impl From<bool> for LockStatus {
+    fn from(locked: bool) -> Self {
+        if locked {
+            LockStatus::Locked
+        } else {
+            LockStatus::Unlocked
+        }
+    }
+}
+
+/// Heuristic cause of a lock.
+///
+/// Detection relies on undocumented session keys and may not cover every
+/// screen-sharing tool or MDM vendor, so a missing reason does not mean the
+/// lock was local.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LockReason {
+    /// The session is not attached to the physical console, e.g. it is being
+    /// driven through screen sharing or remote management.
+    Remote,
+    /// loginwindow reports a managed ("safe") login, as seen when the Mac was
+    /// locked by an MDM command.
+    Managed,
+}
+
+/// Group of events that can be turned off with `Builder::enable_channel`.
+///
+/// Channels for further signals, such as display power, will be added here
+/// as opt-in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Channel {
+    /// Lock and unlock events on
+    /// `window_screen_lock_status://change_session_status`, detected by the
+    /// monitoring thread. Enabled by default.
+    Lock,
+    /// Screensaver on/off on
+    /// `window_screen_lock_status://screensaver_changed`, see
+    /// [`ScreensaverEvent`]. Linux only, when a screensaver backend is
+    /// running. Off by default.
+    Screensaver,
+    /// Best-effort warnings before a lock on
+    /// `window_screen_lock_status://pre_lock`, see [`PreLockEvent`]. macOS
+    /// and Windows only. Off by default.
+    PreLock,
+    /// Presence changes on `window_screen_lock_status://presence_changed`,
+    /// see [`PresenceEvent`]. The idle time is polled at `Builder::poll_interval`:
+    /// on Linux from GNOME's Mutter or KDE's screensaver on the session bus.
+    /// Off by default.
+    Presence,
+}
+
+/// Source the lock state is read from.
+///
+/// Non-exhaustive since the `mock` feature adds a variant, so matches
+/// written without the feature keep compiling with it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "commands", derive(Deserialize))]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum BackendKind {
+    /// Linux: `LockedHint` of the logind session on the system bus.
+    Logind,
+    /// Linux: `org.gnome.ScreenSaver` on the session bus.
+    GnomeScreenSaver,
+    /// Linux: KDE's `org.kde.screensaver` on the session bus.
+    KdeScreenSaver,
+    /// Linux: a generic `org.freedesktop.ScreenSaver` on the session bus.
+    FreedesktopScreenSaver,
+    /// Windows: `WTSRegisterSessionNotification` session change messages.
+    Wts,
+    /// macOS: the `CGSessionCopyCurrentDictionary` session dictionary.
+    CgSession,
+    /// The `mock` feature: transitions reported with `simulate`.
+    #[cfg(feature = "mock")]
+    Mock,
+}
+
+/// What the plugin can detect on the current system.
+#[derive(Clone, Debug, Serialize)]
+pub struct Capabilities {
+    /// Lock/unlock transitions are reported.
+    pub lock: bool,
+    /// Lock events may carry a `reason`.
+    pub lock_reason: bool,
+    /// `screensaver` is reported by `get_full_state`.
+    pub screensaver: bool,
+    /// `idle_ms` and `present` are reported by `get_full_state`.
+    pub idle: bool,
+    /// `display_power` is reported by `get_full_state`.
+    pub display_power: bool,
+    /// `lid` is reported by `get_full_state`.
+    pub lid: bool,
+    /// `is_remote` is reported by `get_full_state`.
+    pub is_remote: bool,
+    /// Backends found on this system, in order of preference.
+    pub available_backends: Vec<BackendKind>,
+    /// Backend currently used for monitoring, if any.
+    pub active_backend: Option<BackendKind>,
+}
+
+/// Everything the plugin knows about the session, as returned by `get_full_state`.
+///
+/// Fields that can't be detected on the current platform are `null`; see
+/// [`Capabilities`] for which ones are supported.
+#[derive(Clone, Debug, Serialize)]
+pub struct FullState {
+    /// `true` while the session is locked.
+    pub lock: Option<bool>,
+    /// `true` while the screensaver is running.
+    pub screensaver: Option<bool>,
+    /// Time since the last user input in ms, as last polled with
+    /// [`Channel::Presence`].
+    pub idle_ms: Option<u64>,
+    /// `true` while the user counts as present, see [`PresenceEvent`].
+    pub present: Option<bool>,
+    /// `true` while the display is powered on.
+    pub display_power: Option<bool>,
+    /// `true` while the lid is closed.
+    pub lid: Option<bool>,
+    /// `true` when the session is not attached to the physical console.
+    pub is_remote: Option<bool>,
+    pub active_backend: Option<BackendKind>,
+    /// Unix time in ms at which `lock` took its current value.
+    pub since_ms: Option<u64>,
+    /// `true` if the session was already in its current state when the
+    /// plugin started, so the transition was never observed and `since_ms`
+    /// is the plugin start time: the real transition happened earlier.
+    pub approximate: bool,
+}
+
+/// Payload of the `window_screen_lock_status://any_change` event: the
+/// [`FullState`] after a lock, unlock, screensaver, display or presence change.
+/// Only sent when enabled with `Builder::any_change_events`.
+#[derive(Clone, Debug, Serialize)]
+pub struct AnyChangeEvent {
+    #[serde(flatten)]
+    pub state: FullState,
+    pub seq: u64,
+}
+
+/// Session details returned by `get_session_info`.
+#[derive(Clone, Debug, Serialize)]
+pub struct SessionInfo {
+    /// Whether unlocking needs a password, read from the user's settings:
+    /// "require password after sleep or screen saver" on macOS, GNOME's
+    /// `lock-enabled` on Linux, and `ScreenSaverIsSecure` on Windows.
+    ///
+    /// This is a best-effort read that varies a lot between platforms and
+    /// desktops, and policies or MDM profiles may override it, so it is not
+    /// a guarantee. `null` when the setting can't be read.
+    pub requires_password: Option<bool>,
+}
+
+/// Detection latency returned by the `get_stats` command, with the
+/// `latency-metrics` feature.
+///
+/// Latency runs from the moment a backend could first have seen a change to
+/// the emit of the matching `change_session_status` event, i.e. the OS
+/// signal. Delayed unlocks include the unlock debounce.
+/// The `*_us` fields are `None` until the first event.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Stats {
+    /// Number of events measured.
+    pub samples: u64,
+    pub min_latency_us: Option<u64>,
+    pub max_latency_us: Option<u64>,
+    pub avg_latency_us: Option<u64>,
+}
+
+/// Transition counts returned by the `get_lock_metrics` command.
+///
+/// Only transitions sent to the frontend count: not the initial state, and
+/// not an unlock dropped by the unlock debounce or a change between the
+/// unlocked statuses of `Builder::detailed_status`.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct LockMetrics {
+    pub lock_count: u64,
+    pub unlock_count: u64,
+    /// Unix time in ms at which the last counted transition was detected.
+    pub last_change_ms: Option<u64>,
+    /// Status last sent to the frontend, `None` before the first one.
+    pub current: Option<LockStatus>,
+}
@@ -0,0 +1,66 @@
+use crate::dispatch;
+use crate::logging::info;
+use crate::state::SharedState;
+use std::time::Duration;
+use tauri::{AppHandle, Manager, Runtime};
+
+/// Polls `idle_time` at the poll interval and reports presence changes,
+/// until the plugin is stopped. A lock, an unlock or a new idle threshold
+/// wakes it up early, so presence is re-evaluated right away.
+pub(crate) fn watch<R: Runtime>(
+    app: AppHandle<R>,
+    mut idle_time: impl FnMut() -> Option<Duration>,
+) {
+    info!("Starting new thread for presence detection...");
+    let state = app.state::<SharedState>();
+    // Each poll is one idle time read: a D-Bus call on a kept connection on Linux, else a syscall.
+    while !state.is_stopped() {
+        if let Some(present) = state.record_idle(idle_time()) {
+            dispatch::presence_changed(&app, present);
+        }
+        state.wait(state.poll_interval());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events;
+    use std::sync::mpsc;
+    use std::thread;
+    use tauri::test::{mock_builder, mock_context, noop_assets};
+    use tauri::Listener;
+
+    #[test]
+    fn new_threshold_is_applied_without_waiting_for_a_poll() {
+        let app = mock_builder()
+            .plugin(
+                crate::Builder::new()
+                    .auto_start(false)
+                    .poll_interval(Duration::from_secs(60))
+                    .build(),
+            )
+            .build(mock_context(noop_assets()))
+            .unwrap();
+        let (sender, changes) = mpsc::channel();
+        app.listen(events::PRESENCE_CHANGED, move |event| {
+            let _ = sender.send(event.payload().to_owned());
+        });
+        let handle = app.handle().clone();
+        thread::spawn(move || watch(handle, || Some(Duration::from_secs(2))));
+
+        let timeout = Duration::from_secs(5);
+        assert!(changes
+            .recv_timeout(timeout)
+            .unwrap()
+            .contains("\"present\":true"));
+        let state = app.state::<SharedState>();
+        state.set_idle_threshold(1000).unwrap();
+        assert!(changes
+            .recv_timeout(timeout)
+            .unwrap()
+            .contains("\"present\":false"));
+        state.stop();
+        state.wake();
+    }
+}
@@ -0,0 +1,656 @@
+// Several getters only back the `invoke` commands, and others only the
+// platform backends, which the `mock` feature doesn't start.
+#![cfg_attr(
+    any(
+        not(feature = "commands"),
+        feature = "mock",
+        not(any(linux_backend, macos_backend, windows_backend))
+    ),
+    allow(dead_code)
+)]
+
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex, MutexGuard, OnceLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+#[cfg(feature = "async")]
+use tokio::sync::broadcast;
+
+use crate::dispatch::Delivery;
+use crate::error::{Result, ScreenLockError};
+use crate::events;
+use crate::logging::{info, warn};
+#[cfg(feature = "latency-metrics")]
+use crate::models::Stats;
+use crate::models::{BackendKind, FullState, LockStatus, PersistedState};
+
+/// Idle time after which the user is no longer considered present.
+pub const DEFAULT_IDLE_THRESHOLD_MS: u64 = 5 * 60 * 1000;
+/// Smallest idle threshold accepted by `set_idle_threshold`.
+pub const MIN_IDLE_THRESHOLD_MS: u64 = 1000;
+/// Default for `Builder::poll_interval`.
+pub const DEFAULT_POLL_INTERVAL_MS: u64 = 1000;
+/// Shortest poll interval; shorter ones are raised to it.
+pub const MIN_POLL_INTERVAL_MS: u64 = 50;
+/// Transitions buffered for each `subscribe` stream before it lags.
+#[cfg(feature = "async")]
+const SUBSCRIBER_CAPACITY: usize = 16;
+/// Largest unlock debounce accepted by `Builder::try_build`.
+pub const MAX_UNLOCK_DEBOUNCE_MS: u64 = 60 * 1000;
+
+/// Callback set with `Builder::on_raw_read`.
+pub(crate) type RawReadHook = Box<dyn Fn(BackendKind, LockStatus, Duration) + Send + Sync>;
+/// Callback set with `Builder::on_change`.
+pub(crate) type ChangeHook = Box<dyn Fn(LockStatus) + Send + Sync>;
+/// Callback set with `Builder::persist_with`.
+pub(crate) type PersistHook = Box<dyn Fn(&PersistedState) + Send + Sync>;
+/// Window label predicate set with `Builder::emit_filter`.
+pub(crate) type EmitFilter = Box<dyn Fn(&str) -> bool + Send + Sync>;
+/// Starts the monitoring threads of a plugin built with
+/// `Builder::auto_start(false)`.
+pub(crate) type Starter = Box<dyn FnOnce() + Send>;
+
+/// Last known session state, as recorded by the monitoring threads.
+#[derive(Clone, Copy, Debug)]
+pub struct SessionSnapshot {
+    pub locked: Option<bool>,
+    pub screensaver: Option<bool>,
+    pub display_power: Option<bool>,
+    pub is_remote: Option<bool>,
+    /// Unix time in ms of the last lock transition.
+    pub since_ms: Option<u64>,
+    /// `since_ms` is the plugin start time rather than an observed transition.
+    pub approximate: bool,
+    /// Time since the last user input, as last polled for presence.
+    pub idle_ms: Option<u64>,
+    /// Whether the user counts as present, see [`SharedState::record_idle`].
+    pub present: Option<bool>,
+}
+
+/// Running totals behind [`Stats`].
+#[cfg(feature = "latency-metrics")]
+struct Latency {
+    samples: u64,
+    min: Duration,
+    max: Duration,
+    total: Duration,
+}
+
+/// Configuration and session state of one plugin instance, shared between
+/// the plugin commands and the monitoring threads through the app's managed
+/// state.
+pub struct SharedState {
+    idle_threshold_ms: AtomicU64,
+    unlock_debounce_ms: AtomicU64,
+    poll_interval_ms: AtomicU64,
+    replay_on_window_create: AtomicUsize,
+    annotate_requires_password: AtomicBool,
+    raw_events: AtomicBool,
+    any_change_events: AtomicBool,
+    initial_status: AtomicBool,
+    detailed_status: AtomicBool,
+    screensaver_events: AtomicBool,
+    presence_events: AtomicBool,
+    /// Set by `stop`; the monitoring threads exit and aren't restarted.
+    stopped: AtomicBool,
+    on_raw_read: OnceLock<RawReadHook>,
+    on_change: OnceLock<ChangeHook>,
+    /// Channel of the lock and unlock events, set with `Builder::event_name`.
+    event_name: OnceLock<String>,
+    /// Window events are sent to, set with `Builder::emit_to`.
+    emit_to: OnceLock<String>,
+    emit_filter: OnceLock<EmitFilter>,
+    persist_with: OnceLock<PersistHook>,
+    /// Taken by the first `start`, so the monitor is started at most once.
+    starter: Mutex<Option<Starter>>,
+    /// Lock state restored with `Builder::restore_from`, until a backend
+    /// reads the live one.
+    restored: Mutex<Option<bool>>,
+    #[cfg(feature = "debug-notifications")]
+    debug_os_notifications: AtomicBool,
+    available_backends: Mutex<Vec<BackendKind>>,
+    active_backend: Mutex<Option<BackendKind>>,
+    preferred_backend: Mutex<Option<BackendKind>>,
+    session: Mutex<SessionSnapshot>,
+    wakeup: Mutex<bool>,
+    wakeup_cvar: Condvar,
+    /// `None` until the monitor has started, then whether it found a backend.
+    ready: Mutex<Option<bool>>,
+    ready_cvar: Condvar,
+    /// Sequence number of the next emitted event, shared by all channels.
+    seq: AtomicU64,
+    delivery: Mutex<Delivery>,
+    /// Delivered transitions, fanned out to the `subscribe` streams.
+    #[cfg(feature = "async")]
+    transitions: broadcast::Sender<LockStatus>,
+    /// Unix time in ms at which the plugin was initialized.
+    #[cfg_attr(not(any(linux_backend, macos_backend)), allow(dead_code))]
+    started_ms: u64,
+    #[cfg(feature = "latency-metrics")]
+    latency: Mutex<Latency>,
+}
+
+impl SharedState {
+    pub(crate) fn new() -> Self {
+        Self {
+            idle_threshold_ms: AtomicU64::new(DEFAULT_IDLE_THRESHOLD_MS),
+            unlock_debounce_ms: AtomicU64::new(0),
+            poll_interval_ms: AtomicU64::new(DEFAULT_POLL_INTERVAL_MS),
+            replay_on_window_create: AtomicUsize::new(1),
+            annotate_requires_password: AtomicBool::new(false),
+            raw_events: AtomicBool::new(false),
+            any_change_events: AtomicBool::new(false),
+            initial_status: AtomicBool::new(true),
+            detailed_status: AtomicBool::new(false),
+            screensaver_events: AtomicBool::new(false),
+            presence_events: AtomicBool::new(false),
+            stopped: AtomicBool::new(false),
+            on_raw_read: OnceLock::new(),
+            on_change: OnceLock::new(),
+            event_name: OnceLock::new(),
+            emit_to: OnceLock::new(),
+            emit_filter: OnceLock::new(),
+            persist_with: OnceLock::new(),
+            starter: Mutex::new(None),
+            restored: Mutex::new(None),
+            #[cfg(feature = "debug-notifications")]
+            debug_os_notifications: AtomicBool::new(false),
+            available_backends: Mutex::new(Vec::new()),
+            active_backend: Mutex::new(None),
+            preferred_backend: Mutex::new(None),
+            session: Mutex::new(SessionSnapshot {
+                locked: None,
+                screensaver: None,
+                display_power: None,
+                is_remote: None,
+                since_ms: None,
+                approximate: false,
+                idle_ms: None,
+                present: None,
+            }),
+            wakeup: Mutex::new(false),
+            wakeup_cvar: Condvar::new(),
+            ready: Mutex::new(None),
+            ready_cvar: Condvar::new(),
+            seq: AtomicU64::new(0),
+            delivery: Mutex::new(Delivery::new()),
+            #[cfg(feature = "async")]
+            transitions: broadcast::channel(SUBSCRIBER_CAPACITY).0,
+            started_ms: now_ms(),
+            #[cfg(feature = "latency-metrics")]
+            latency: Mutex::new(Latency {
+                samples: 0,
+                min: Duration::MAX,
+                max: Duration::ZERO,
+                total: Duration::ZERO,
+            }),
+        }
+    }
+
+    pub fn idle_threshold(&self) -> Duration {
+        Duration::from_millis(self.idle_threshold_ms.load(Ordering::Relaxed))
+    }
+
+    /// Updates the idle threshold and wakes the monitoring loops so presence
+    /// is re-evaluated against the new value right away.
+    pub fn set_idle_threshold(&self, ms: u64) -> Result<()> {
+        if ms < MIN_IDLE_THRESHOLD_MS {
+            return Err(ScreenLockError::InvalidArgument(format!(
+                "Idle threshold must be at least {}ms, got {}ms",
+                MIN_IDLE_THRESHOLD_MS, ms
+            )));
+        }
+        self.idle_threshold_ms.store(ms, Ordering::Relaxed);
+        self.wake();
+        Ok(())
+    }
+
+    pub fn unlock_debounce(&self) -> Option<Duration> {
+        match self.unlock_debounce_ms.load(Ordering::Relaxed) {
+            0 => None,
+            ms => Some(Duration::from_millis(ms)),
+        }
+    }
+
+    pub fn set_unlock_debounce(&self, debounce: Option<Duration>) {
+        let ms = debounce.map_or(0, |debounce| debounce.as_millis() as u64);
+        self.unlock_debounce_ms.store(ms, Ordering::Relaxed);
+    }
+
+    #[cfg(any(linux_backend, macos_backend, windows_backend))]
+    pub fn poll_interval(&self) -> Duration {
+        Duration::from_millis(self.poll_interval_ms.load(Ordering::Relaxed))
+    }
+
+    /// Sets the poll interval, raised to [`MIN_POLL_INTERVAL_MS`] if shorter.
+    pub fn set_poll_interval(&self, interval: Duration) {
+        let ms = (interval.as_millis() as u64).max(MIN_POLL_INTERVAL_MS);
+        self.poll_interval_ms.store(ms, Ordering::Relaxed);
+    }
+
+    /// Number of past events replayed to a newly loaded window.
+    pub fn replay_on_window_create(&self) -> usize {
+        self.replay_on_window_create.load(Ordering::Relaxed)
+    }
+
+    pub fn set_replay_on_window_create(&self, count: usize) {
+        self.replay_on_window_create.store(count, Ordering::Relaxed);
+    }
+
+    pub fn annotate_requires_password(&self) -> bool {
+        self.annotate_requires_password.load(Ordering::Relaxed)
+    }
+
+    pub fn set_annotate_requires_password(&self, enabled: bool) {
+        self.annotate_requires_password
+            .store(enabled, Ordering::Relaxed);
+    }
+
+    #[cfg(any(linux_backend, windows_backend))]
+    pub fn raw_events(&self) -> bool {
+        self.raw_events.load(Ordering::Relaxed)
+    }
+
+    pub fn set_raw_events(&self, enabled: bool) {
+        self.raw_events.store(enabled, Ordering::Relaxed);
+    }
+
+    pub fn any_change_events(&self) -> bool {
+        self.any_change_events.load(Ordering::Relaxed)
+    }
+
+    pub fn set_any_change_events(&self, enabled: bool) {
+        self.any_change_events.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Whether the state read at startup is emitted, see
+    /// `Builder::initial_status`. Windows can't read the state at startup.
+    #[cfg_attr(not(any(linux_backend, macos_backend)), allow(dead_code))]
+    pub fn initial_status(&self) -> bool {
+        self.initial_status.load(Ordering::Relaxed)
+    }
+
+    pub fn set_initial_status(&self, enabled: bool) {
+        self.initial_status.store(enabled, Ordering::Relaxed);
+    }
+
+    pub fn detailed_status(&self) -> bool {
+        self.detailed_status.load(Ordering::Relaxed)
+    }
+
+    pub fn set_detailed_status(&self, enabled: bool) {
+        self.detailed_status.store(enabled, Ordering::Relaxed);
+    }
+
+    pub(crate) fn on_raw_read(&self) -> Option<&RawReadHook> {
+        self.on_raw_read.get()
+    }
+
+    pub(crate) fn set_on_raw_read(&self, hook: RawReadHook) {
+        let _ = self.on_raw_read.set(hook);
+    }
+
+    pub(crate) fn on_change(&self) -> Option<&ChangeHook> {
+        self.on_change.get()
+    }
+
+    pub(crate) fn set_on_change(&self, hook: ChangeHook) {
+        let _ = self.on_change.set(hook);
+    }
+
+    /// Channel the lock and unlock events are emitted on, by default
+    /// [`events::CHANGE_SESSION_STATUS`].
+    pub fn event_name(&self) -> &str {
+        self.event_name
+            .get()
+            .map_or(events::CHANGE_SESSION_STATUS, String::as_str)
+    }
+
+    pub fn set_event_name(&self, name: String) {
+        let _ = self.event_name.set(name);
+    }
+
+    pub(crate) fn emit_target(&self) -> Option<&str> {
+        self.emit_to.get().map(String::as_str)
+    }
+
+    pub(crate) fn set_emit_target(&self, label: String) {
+        let _ = self.emit_to.set(label);
+    }
+
+    pub(crate) fn emit_filter(&self) -> Option<&EmitFilter> {
+        self.emit_filter.get()
+    }
+
+    pub(crate) fn set_emit_filter(&self, filter: EmitFilter) {
+        let _ = self.emit_filter.set(filter);
+    }
+
+    pub(crate) fn persist_with(&self) -> Option<&PersistHook> {
+        self.persist_with.get()
+    }
+
+    pub(crate) fn set_persist_with(&self, hook: PersistHook) {
+        let _ = self.persist_with.set(hook);
+    }
+
+    pub(crate) fn set_starter(&self, starter: Starter) {
+        *lock(&self.starter) = Some(starter);
+    }
+
+    pub(crate) fn take_starter(&self) -> Option<Starter> {
+        lock(&self.starter).take()
+    }
+
+    /// Whether events go to the window or webview labelled `label`.
+    pub(crate) fn emits_to(&self, label: &str) -> bool {
+        match self.emit_target() {
+            Some(target) => label == target,
+            None => self.emit_filter().map_or(true, |filter| filter(label)),
+        }
+    }
+
+    #[cfg(linux_backend)]
+    pub fn screensaver_events(&self) -> bool {
+        self.screensaver_events.load(Ordering::Relaxed)
+    }
+
+    pub fn set_screensaver_events(&self, enabled: bool) {
+        self.screensaver_events.store(enabled, Ordering::Relaxed);
+    }
+
+    pub fn presence_events(&self) -> bool {
+        self.presence_events.load(Ordering::Relaxed)
+    }
+
+    pub fn set_presence_events(&self, enabled: bool) {
+        self.presence_events.store(enabled, Ordering::Relaxed);
+    }
+
+    #[cfg(feature = "debug-notifications")]
+    pub fn debug_os_notifications(&self) -> bool {
+        self.debug_os_notifications.load(Ordering::Relaxed)
+    }
+
+    #[cfg(feature = "debug-notifications")]
+    pub fn set_debug_os_notifications(&self, enabled: bool) {
+        self.debug_os_notifications
+            .store(enabled, Ordering::Relaxed);
+    }
+
+    pub fn available_backends(&self) -> Vec<BackendKind> {
+        lock(&self.available_backends).clone()
+    }
+
+    pub fn set_available_backends(&self, backends: Vec<BackendKind>) {
+        *lock(&self.available_backends) = backends;
+    }
+
+    pub fn active_backend(&self) -> Option<BackendKind> {
+        *lock(&self.active_backend)
+    }
+
+    pub fn set_active_backend(&self, backend: Option<BackendKind>) {
+        *lock(&self.active_backend) = backend;
+    }
+
+    pub fn set_preferred_backend(&self, backend: Option<BackendKind>) {
+        *lock(&self.preferred_backend) = backend;
+    }
+
+    /// Records the backends that initialized successfully and picks the one
+    /// to monitor with: the preferred backend when it is among them,
+    /// otherwise the first one.
+    pub fn select_backend(&self, available: Vec<BackendKind>) -> Option<BackendKind> {
+        let preferred = *lock(&self.preferred_backend);
+        let selected = match preferred {
+            Some(preferred) if available.contains(&preferred) => Some(preferred),
+            _ => available.first().copied(),
+        };
+        match (preferred, selected) {
+            (Some(preferred), Some(selected)) if preferred == selected => {
+                info!("Using preferred backend {:?}", selected)
+            }
+            (Some(preferred), _) => warn!(
+                "Preferred backend {:?} is not available, using {:?}",
+                preferred, selected
+            ),
+            (None, Some(selected)) => info!("Using backend {:?}", selected),
+            (None, None) => {}
+        }
+        self.set_available_backends(available);
+        self.set_active_backend(selected);
+        selected
+    }
+
+    pub fn session(&self) -> SessionSnapshot {
+        *lock(&self.session)
+    }
+
+    /// The recorded session state, as returned by `get_full_state`.
+    pub fn full_state(&self) -> FullState {
+        let session = self.session();
+        FullState {
+            lock: session.locked,
+            screensaver: session.screensaver,
+            idle_ms: session.idle_ms,
+            present: session.present,
+            display_power: session.display_power,
+            lid: None,
+            is_remote: session.is_remote,
+            active_backend: self.active_backend(),
+            since_ms: session.since_ms,
+            approximate: session.approximate,
+        }
+    }
+
+    /// Records the lock state, stamping `since_ms` when it changes. Returns
+    /// whether it changed.
+    pub fn record_lock(&self, locked: bool) -> bool {
+        let mut session = lock(&self.session);
+        if session.locked == Some(locked) {
+            return false;
+        }
+        session.locked = Some(locked);
+        session.since_ms = Some(now_ms());
+        session.approximate = false;
+        true
+    }
+
+    /// Seeds the lock state from a previous run. It is compared with the
+    /// first state a backend reads, see [`SharedState::take_restored`].
+    pub fn record_restored_lock(&self, locked: bool, since_ms: Option<u64>) {
+        let mut session = lock(&self.session);
+        session.locked = Some(locked);
+        session.since_ms = since_ms;
+        session.approximate = false;
+        *lock(&self.restored) = Some(locked);
+    }
+
+    /// Returns the restored lock state once, on the first read after the
+    /// restore.
+    pub(crate) fn take_restored(&self) -> Option<bool> {
+        lock(&self.restored).take()
+    }
+
+    /// Records the lock state read when monitoring starts. The session has
+    /// been in that state since before the plugin started, so `since_ms` is
+    /// set to the start time and marked approximate.
+    #[cfg_attr(not(any(linux_backend, macos_backend)), allow(dead_code))]
+    pub fn record_initial_lock(&self, locked: bool) {
+        let mut session = lock(&self.session);
+        if session.locked.is_none() {
+            session.locked = Some(locked);
+            session.since_ms = Some(self.started_ms);
+            session.approximate = true;
+        }
+    }
+
+    /// Records the screensaver state and returns whether it changed.
+    #[cfg(any(linux_backend, macos_backend, feature = "mock"))]
+    pub fn record_screensaver(&self, active: bool) -> bool {
+        let mut session = lock(&self.session);
+        let changed = session.screensaver != Some(active);
+        session.screensaver = Some(active);
+        changed
+    }
+
+    /// Records whether the display is on and returns whether it changed.
+    #[cfg(any(windows_backend, feature = "mock"))]
+    pub fn record_display_power(&self, on: bool) -> bool {
+        let mut session = lock(&self.session);
+        let changed = session.display_power != Some(on);
+        session.display_power = Some(on);
+        changed
+    }
+
+    /// The status to report for the lock state `locked`: refined with the
+    /// screensaver and display state when detailed statuses are enabled.
+    pub fn status(&self, locked: bool) -> LockStatus {
+        if locked || !self.detailed_status() {
+            return LockStatus::from(locked);
+        }
+        let session = self.session();
+        if session.display_power == Some(false) {
+            LockStatus::DisplaySleep
+        } else if session.screensaver == Some(true) {
+            LockStatus::ScreensaverActive
+        } else {
+            LockStatus::Unlocked
+        }
+    }
+
+    /// Records the polled idle time and returns the presence if it changed.
+    /// The user is present while the session isn't locked and has been idle
+    /// for less than the idle threshold; unknown if the idle time is.
+    pub fn record_idle(&self, idle: Option<Duration>) -> Option<bool> {
+        let threshold = self.idle_threshold();
+        let mut session = lock(&self.session);
+        let present = match (session.locked, idle) {
+            (Some(true), _) => Some(false),
+            (_, Some(idle)) => Some(idle < threshold),
+            (_, None) => None,
+        };
+        session.idle_ms = idle.map(|idle| idle.as_millis() as u64);
+        let changed = session.present != present;
+        session.present = present;
+        present.filter(|_| changed)
+    }
+
+    #[cfg(macos_backend)]
+    pub fn record_remote(&self, is_remote: Option<bool>) {
+        lock(&self.session).is_remote = is_remote;
+    }
+
+    /// Marks the plugin as stopped and returns whether it already was.
+    pub(crate) fn stop(&self) -> bool {
+        self.stopped.swap(true, Ordering::SeqCst)
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::SeqCst)
+    }
+
+    /// Interrupts a pending `wait` on the monitoring threads.
+    pub fn wake(&self) {
+        let mut woken = lock(&self.wakeup);
+        *woken = true;
+        self.wakeup_cvar.notify_all();
+    }
+
+    /// Sleeps for `timeout` or until `wake` is called, whichever comes first.
+    pub fn wait(&self, timeout: Duration) {
+        let woken = lock(&self.wakeup);
+        let mut woken = self
+            .wakeup_cvar
+            .wait_timeout_while(woken, timeout, |woken| !*woken)
+            .unwrap_or_else(|e| e.into_inner())
+            .0;
+        *woken = false;
+    }
+
+    /// Records whether the monitor is running with a backend and releases the
+    /// callers blocked in `wait_ready`.
+    pub fn set_ready(&self, ready: bool) {
+        let mut current = lock(&self.ready);
+        *current = Some(ready);
+        self.ready_cvar.notify_all();
+    }
+
+    /// Blocks until the monitor has started or `timeout` has elapsed, and
+    /// returns whether a backend is running, or `None` on timeout. Waits
+    /// without a limit if `timeout` is `None`.
+    pub fn wait_ready(&self, timeout: Option<Duration>) -> Option<bool> {
+        let ready = lock(&self.ready);
+        let ready = match timeout {
+            Some(timeout) => {
+                self.ready_cvar
+                    .wait_timeout_while(ready, timeout, |ready| ready.is_none())
+                    .unwrap_or_else(|e| e.into_inner())
+                    .0
+            }
+            None => self
+                .ready_cvar
+                .wait_while(ready, |ready| ready.is_none())
+                .unwrap_or_else(|e| e.into_inner()),
+        };
+        *ready
+    }
+
+    /// Returns the sequence number for the next emitted event.
+    pub(crate) fn next_seq(&self) -> u64 {
+        self.seq.fetch_add(1, Ordering::Relaxed)
+    }
+
+    #[cfg(feature = "latency-metrics")]
+    pub fn record_latency(&self, latency: Duration) {
+        let mut totals = lock(&self.latency);
+        totals.samples += 1;
+        totals.min = totals.min.min(latency);
+        totals.max = totals.max.max(latency);
+        totals.total = totals.total.saturating_add(latency);
+    }
+
+    #[cfg(feature = "latency-metrics")]
+    pub fn stats(&self) -> Stats {
+        let totals = lock(&self.latency);
+        if totals.samples == 0 {
+            return Stats::default();
+        }
+        let micros = |latency: Duration| Some(latency.as_micros() as u64);
+        Stats {
+            samples: totals.samples,
+            min_latency_us: micros(totals.min),
+            max_latency_us: micros(totals.max),
+            avg_latency_us: Some((totals.total.as_micros() / u128::from(totals.samples)) as u64),
+        }
+    }
+
+    /// Sends a delivered transition to the subscribers, if there are any.
+    #[cfg(feature = "async")]
+    pub(crate) fn publish(&self, status: LockStatus) {
+        let _ = self.transitions.send(status);
+    }
+
+    #[cfg(feature = "async")]
+    pub(crate) fn subscribe(&self) -> broadcast::Receiver<LockStatus> {
+        self.transitions.subscribe()
+    }
+
+    pub(crate) fn delivery(&self) -> MutexGuard<'_, Delivery> {
+        lock(&self.delivery)
+    }
+}
+
+/// Locks a mutex, ignoring poisoning: the guarded values stay valid even if a
+/// monitoring thread panicked while holding the lock.
+pub(crate) fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Current Unix time in milliseconds.
+pub fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or_default()
+}
@@ -0,0 +1,75 @@
+use crate::dispatch;
+use crate::logging::warn;
+use crate::models::LockStatus;
+use crate::state::SharedState;
+use futures_util::stream::{self, Stream};
+use std::future::Future;
+use tauri::{Manager, Runtime};
+use tokio::sync::broadcast::error::RecvError;
+
+/// Returns the lock and unlock transitions delivered from now on, the same
+/// ones sent on `window_screen_lock_status://change_session_status`. Each
+/// call gets its own stream, and the monitoring threads never wait for one.
+/// Needs the `async` feature.
+///
+/// A stream that falls more than 16 transitions behind skips the oldest and
+/// logs a warning, then continues with the ones still buffered. Without
+/// `Builder::detailed_status` transitions alternate, so two equal items in a
+/// row mean some were skipped; the last item is always the current state.
+///
+/// Panics if the plugin isn't registered on `manager`.
+pub fn subscribe<R: Runtime, M: Manager<R>>(
+    manager: &M,
+) -> impl Stream<Item = LockStatus> + Send + 'static {
+    let receiver = manager.state::<SharedState>().subscribe();
+    stream::unfold(receiver, |mut receiver| async move {
+        loop {
+            match receiver.recv().await {
+                Ok(status) => return Some((status, receiver)),
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!("Lock status stream lagged, skipped {} transitions", skipped)
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    })
+}
+
+/// Resolves once the session reaches `status`, right away if it's already
+/// there. `status` is matched exactly as delivered, so with
+/// `Builder::detailed_status` an unlocked session may be reported as
+/// `ScreensaverActive` or `DisplaySleep` rather than `Unlocked`. Needs the
+/// `async` feature.
+///
+/// Built on the same transitions as [`subscribe`], without polling. The
+/// current status is read when this is called, not when the future is
+/// first polled, and the future may be dropped at any time, e.g. in a
+/// `select!` or on a timeout. After [`crate::stop`] no more transitions
+/// arrive, so it only resolves if `status` was already reached.
+///
+/// Panics if the plugin isn't registered on `manager`.
+pub fn wait_for<R: Runtime, M: Manager<R>>(
+    manager: &M,
+    status: LockStatus,
+) -> impl Future<Output = ()> + Send + 'static {
+    let state = manager.state::<SharedState>();
+    // Transitions are published in delivery order after being recorded as
+    // delivered, so subscribing first misses none between the two.
+    let mut receiver = state.subscribe();
+    let reached = dispatch::delivered(&state) == Some(status);
+    async move {
+        if reached {
+            return;
+        }
+        loop {
+            match receiver.recv().await {
+                Ok(next) if next == status => return,
+                Ok(_) => {}
+                // The buffered transitions still end with the current state.
+                Err(RecvError::Lagged(_)) => {}
+                // Only once the app is gone, when `status` can't be reached.
+                Err(RecvError::Closed) => return std::future::pending().await,
+            }
+        }
+    }
+}
@@ -0,0 +1,104 @@
+use crate::dispatch;
+use crate::error::ScreenLockError;
+use crate::logging::warn;
+use crate::state::SharedState;
+use std::any::Any;
+use std::panic::{self, AssertUnwindSafe};
+use std::thread;
+use std::time::Duration;
+use tauri::{AppHandle, Manager, Runtime};
+
+/// Restarts after a panic when no `restart_on_panic` limit is configured.
+pub const DEFAULT_MAX_RESTARTS: u32 = 3;
+
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Name of the thread running the lock monitor of every platform.
+const MONITOR_THREAD: &str = "screen-lock-monitor";
+
+/// Runs `monitor` with `app` on a new thread, catching panics.
+///
+/// A panic is logged and reported through the `monitor_error` event, then
+/// `monitor` is restarted with an exponential backoff until it has been
+/// restarted `max_restarts` times, or the plugin is stopped.
+pub(crate) fn spawn<R, F>(app: AppHandle<R>, max_restarts: u32, monitor: F)
+where
+    R: Runtime,
+    F: Fn(&AppHandle<R>) + Send + 'static,
+{
+    let handle = app.clone();
+    let spawned = spawn_named(&handle, MONITOR_THREAD, true, move || {
+        let mut restarts = 0;
+        loop {
+            let payload = match panic::catch_unwind(AssertUnwindSafe(|| monitor(&app))) {
+                Ok(()) => break,
+                Err(payload) => payload,
+            };
+
+            let message = panic_message(payload.as_ref());
+            let stopped = || app.state::<SharedState>().is_stopped();
+            let will_restart = restarts < max_restarts && !stopped();
+            warn!(
+                "Screen lock monitor panicked: {} ({} restarts so far)",
+                message, restarts
+            );
+            dispatch::monitor_error(&app, message, restarts, will_restart);
+            if !will_restart {
+                break;
+            }
+
+            thread::sleep(backoff(restarts));
+            if stopped() {
+                break;
+            }
+            restarts += 1;
+        }
+    });
+    if !spawned {
+        handle.state::<SharedState>().set_ready(false);
+    }
+}
+
+/// Runs `f` on a new thread called `name`. A failure to spawn it is logged
+/// and reported through the `error` event, `fatal` if lock detection can't
+/// go on without the thread, and `false` returned.
+pub(crate) fn spawn_named<R: Runtime>(
+    app: &AppHandle<R>,
+    name: &str,
+    fatal: bool,
+    f: impl FnOnce() + Send + 'static,
+) -> bool {
+    match thread::Builder::new().name(name.into()).spawn(f) {
+        Ok(_) => true,
+        Err(e) => {
+            dispatch::backend_error(
+                app,
+                None,
+                ScreenLockError::PlatformError(format!(
+                    "Failed to spawn the {} thread: {}",
+                    name, e
+                )),
+                fatal,
+            );
+            false
+        }
+    }
+}
+
+/// Delay before the next attempt after `restarts` earlier ones: one second,
+/// doubling up to 30 seconds.
+pub(crate) fn backoff(restarts: u32) -> Duration {
+    Duration::from_secs(1)
+        .checked_mul(1 << restarts.min(16))
+        .map_or(MAX_BACKOFF, |backoff| backoff.min(MAX_BACKOFF))
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    match payload.downcast_ref::<&str>() {
+        Some(message) => message.to_string(),
+        None => match payload.downcast_ref::<String>() {
+            Some(message) => message.clone(),
+            None => "unknown panic".to_string(),
+        },
+    }
+}
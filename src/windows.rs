@@ -0,0 +1,401 @@
+use crate::dispatch;
+use crate::error::ScreenLockError;
+use crate::logging::{info, warn};
+use crate::models::{BackendKind, RawValue};
+use crate::state::SharedState;
+use crate::wts::session_locked;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Manager, Runtime};
+use windows::{
+    core::*,
+    Win32::Foundation::*,
+    Win32::System::{
+        LibraryLoader::*,
+        Power::{
+            RegisterPowerSettingNotification, UnregisterPowerSettingNotification,
+            POWERBROADCAST_SETTING,
+        },
+        Registry::{
+            RegGetValueW, HKEY, HKEY_CURRENT_USER, HKEY_LOCAL_MACHINE, RRF_RT_REG_DWORD,
+            RRF_RT_REG_SZ,
+        },
+        RemoteDesktop::{
+            WTSRegisterSessionNotification, WTSUnRegisterSessionNotification,
+            NOTIFY_FOR_ALL_SESSIONS, NOTIFY_FOR_THIS_SESSION,
+        },
+        SystemInformation::GetTickCount,
+        SystemServices::GUID_CONSOLE_DISPLAY_STATE,
+        Threading::GetCurrentThreadId,
+    },
+    Win32::UI::Input::KeyboardAndMouse::{GetLastInputInfo, LASTINPUTINFO},
+    Win32::UI::WindowsAndMessaging::*,
+};
+
+/// How long before the expected lock `pre_lock` is emitted.
+const PRE_LOCK_WARNING: Duration = Duration::from_secs(30);
+/// Thread message carrying a display state change from `wndproc` to the
+/// message loop, with the `GUID_CONSOLE_DISPLAY_STATE` value as `wParam`.
+const WM_DISPLAY_STATE: u32 = WM_APP + 1;
+
+/// Thread running the message loop, so `stop` can post `WM_QUIT` to it.
+#[derive(Default)]
+pub(crate) struct MessageLoop {
+    /// `0` while no message loop is running.
+    thread_id: AtomicU32,
+}
+
+/// Ends the message loop, which then unregisters the session notification.
+/// The pre-lock watcher exits at its next poll.
+pub(crate) fn stop<R: Runtime>(app: &AppHandle<R>) {
+    let thread_id = app
+        .state::<MessageLoop>()
+        .thread_id
+        .swap(0, Ordering::SeqCst);
+    if thread_id != 0 {
+        if let Err(e) = unsafe { PostThreadMessageA(thread_id, WM_QUIT, WPARAM(0), LPARAM(0)) } {
+            warn!("Failed to stop the message loop: {}", e);
+        }
+    }
+}
+
+fn register_session_notification(hwnd: HWND, current_session_only: bool) -> Result<()> {
+    let flags = if current_session_only {
+        NOTIFY_FOR_THIS_SESSION
+    } else {
+        NOTIFY_FOR_ALL_SESSIONS
+    };
+    unsafe { WTSRegisterSessionNotification(hwnd, flags) }
+}
+
+/// Reports a failure that ends Windows monitoring.
+fn fatal_error<R: Runtime>(app: &AppHandle<R>, message: String) {
+    let state = app.state::<SharedState>();
+    state.set_active_backend(None);
+    state.set_ready(false);
+    dispatch::backend_error(
+        app,
+        Some(BackendKind::Wts),
+        ScreenLockError::PlatformError(message),
+        true,
+    );
+}
+
+/// Registers the class of the hidden message window. When the monitor starts
+/// again in the same process, e.g. after a restart, the class is already
+/// registered with the same `wndproc` and is reused.
+fn register_window_class(wc: &WNDCLASSA) -> Result<()> {
+    if unsafe { RegisterClassA(wc) } != 0 {
+        return Ok(());
+    }
+    let error = Error::from_win32();
+    if error.code() == ERROR_CLASS_ALREADY_EXISTS.to_hresult() {
+        info!("Window class is already registered, reusing it");
+        Ok(())
+    } else {
+        Err(error)
+    }
+}
+
+/// `WM_POWERBROADCAST` is sent to the window rather than posted, so it
+/// never reaches the message loop. The display state is forwarded to it as
+/// a `WM_DISPLAY_STATE` thread message.
+extern "system" fn wndproc(window: HWND, message: u32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+    unsafe {
+        match message {
+            WM_POWERBROADCAST if wparam.0 as u32 == PBT_POWERSETTINGCHANGE => {
+                let setting = &*(lparam.0 as *const POWERBROADCAST_SETTING);
+                if setting.PowerSetting == GUID_CONSOLE_DISPLAY_STATE {
+                    let _ = PostThreadMessageA(
+                        GetCurrentThreadId(),
+                        WM_DISPLAY_STATE,
+                        WPARAM(setting.Data[0] as usize),
+                        LPARAM(0),
+                    );
+                }
+                LRESULT(1)
+            }
+            _ => DefWindowProcA(window, message, wparam, lparam),
+        }
+    }
+}
+
+/// Runs the message loop. With `current_session_only`, only lock changes of
+/// the session this process runs in are reported, and with
+/// `remote_session_events` remote connects and disconnects count as well.
+pub(crate) fn monitor<R: Runtime>(
+    app: &AppHandle<R>,
+    current_session_only: bool,
+    remote_session_events: bool,
+) {
+    unsafe {
+        info!("Starting new thread for Windows screen lock monitoring...");
+        let state = app.state::<SharedState>();
+        state.select_backend(vec![BackendKind::Wts]);
+        let instance = match GetModuleHandleA(None) {
+            Ok(instance) => instance,
+            Err(e) => {
+                fatal_error(app, format!("Failed to get module handle: {}", e));
+                return;
+            }
+        };
+
+        // A generic name like "window" may already be taken by another
+        // component of the app with a different `wndproc`.
+        let window_class = s!("window_screen_lock_status");
+
+        // The window is never shown, so it needs no cursor.
+        let wc = WNDCLASSA {
+            hInstance: instance.into(),
+            lpszClassName: window_class,
+            style: CS_HREDRAW | CS_VREDRAW,
+            lpfnWndProc: Some(wndproc),
+            ..Default::default()
+        };
+
+        if let Err(e) = register_window_class(&wc) {
+            fatal_error(app, format!("Failed to register window class: {}", e));
+            return;
+        }
+
+        // Created without `WS_VISIBLE`, so it is never shown. It only
+        // receives the session and power notifications.
+        let hwnd = CreateWindowExA(
+            WINDOW_EX_STYLE::default(),
+            window_class,
+            s!("Window"),
+            WS_OVERLAPPEDWINDOW,
+            CW_USEDEFAULT,
+            CW_USEDEFAULT,
+            CW_USEDEFAULT,
+            CW_USEDEFAULT,
+            None,
+            None,
+            instance,
+            Some(std::ptr::null()),
+        );
+        if hwnd.0 == 0 {
+            fatal_error(
+                app,
+                format!("Failed to create window: {}", Error::from_win32()),
+            );
+            return;
+        }
+        let mut message = MSG::default();
+        if let Err(e) = register_session_notification(hwnd, current_session_only) {
+            let _ = DestroyWindow(hwnd);
+            fatal_error(
+                app,
+                format!("Failed to register for session notifications: {}", e),
+            );
+            return;
+        }
+        // The current display state is sent right after registering.
+        let display_notification = RegisterPowerSettingNotification(
+            HANDLE(hwnd.0),
+            &GUID_CONSOLE_DISPLAY_STATE,
+            DEVICE_NOTIFY_WINDOW_HANDLE.0,
+        )
+        .map_err(|e| {
+            dispatch::backend_error(
+                app,
+                Some(BackendKind::Wts),
+                ScreenLockError::PlatformError(format!(
+                    "Failed to register for display state changes: {}",
+                    e
+                )),
+                false,
+            )
+        })
+        .ok();
+        // Published after the window created the message queue, and checked
+        // against `stop` afterwards so a concurrent call isn't missed.
+        let message_loop = app.state::<MessageLoop>();
+        message_loop
+            .thread_id
+            .store(GetCurrentThreadId(), Ordering::SeqCst);
+        if !state.is_stopped() {
+            dispatch::monitoring_started(app, BackendKind::Wts);
+            // `GetMessageA` blocks until the next message and returns 0 for
+            // `WM_QUIT` and -1 on error, both of which end the loop. Nothing is
+            // polled, and only session and power messages do more than dispatch.
+            while GetMessageA(&mut message, HWND(0), 0, 0).0 > 0 {
+                let detected_at = Instant::now();
+                TranslateMessage(&message);
+                DispatchMessageA(&message);
+                match message.message {
+                    WM_WTSSESSION_CHANGE => session_changed(
+                        app,
+                        message.wParam.0 as u32,
+                        remote_session_events,
+                        detected_at,
+                    ),
+                    WM_DISPLAY_STATE => display_changed(app, message.wParam.0, detected_at),
+                    _ => {}
+                }
+            }
+        }
+        message_loop.thread_id.store(0, Ordering::SeqCst);
+        let _ = WTSUnRegisterSessionNotification(hwnd);
+        if let Some(display_notification) = display_notification {
+            let _ = UnregisterPowerSettingNotification(display_notification);
+        }
+        let _ = DestroyWindow(hwnd);
+        if state.is_stopped() {
+            state.set_active_backend(None);
+            state.set_ready(false);
+        } else {
+            fatal_error(app, format!("Message loop ended: {}", Error::from_win32()));
+        }
+    }
+}
+
+/// Handles a `WM_WTSSESSION_CHANGE` with the `code` from its `wParam`.
+fn session_changed<R: Runtime>(
+    app: &AppHandle<R>,
+    code: u32,
+    remote_session_events: bool,
+    detected_at: Instant,
+) {
+    dispatch::raw_event(app, BackendKind::Wts, RawValue::Code(code));
+    let Some(locked) = session_locked(code, remote_session_events) else {
+        return;
+    };
+    dispatch::raw_read(app, BackendKind::Wts, locked, detected_at);
+    dispatch::status_changed(app, locked, None, detected_at);
+}
+
+/// Handles a `GUID_CONSOLE_DISPLAY_STATE` value: 0 for off, 1 for on and 2
+/// for dimmed, which still counts as on.
+fn display_changed<R: Runtime>(app: &AppHandle<R>, display_state: usize, detected_at: Instant) {
+    if app
+        .state::<SharedState>()
+        .record_display_power(display_state != 0)
+    {
+        dispatch::detail_changed(app, detected_at);
+    }
+}
+
+/// Emits `pre_lock` when the user has been idle for nearly as long as the
+/// lock timeout. Polls at the poll interval and warns once per idle period,
+/// until the plugin is stopped.
+pub(crate) fn watch_pre_lock<R: Runtime>(app: AppHandle<R>) {
+    info!("Starting new thread for Windows pre-lock detection...");
+    let state = app.state::<SharedState>();
+    let mut warned = false;
+    // Each poll costs `GetLastInputInfo` and up to four registry reads for the timeout.
+    while !state.is_stopped() {
+        let lock_in = lock_timeout()
+            .zip(idle_time())
+            .and_then(|(timeout, idle)| timeout.checked_sub(idle));
+        match lock_in {
+            Some(lock_in) if lock_in > PRE_LOCK_WARNING => warned = false,
+            Some(lock_in) if !warned => {
+                warned = true;
+                dispatch::pre_lock(&app, Some(lock_in));
+            }
+            // Already warned in this idle period. Past the timeout a lock
+            // either happened or isn't enforced, and a new idle period only
+            // starts with fresh input.
+            _ => {}
+        }
+        thread::sleep(state.poll_interval());
+    }
+}
+
+/// Time since the last keyboard or mouse input in this session.
+pub(crate) fn idle_time() -> Option<Duration> {
+    let mut info = LASTINPUTINFO {
+        cbSize: std::mem::size_of::<LASTINPUTINFO>() as u32,
+        dwTime: 0,
+    };
+    if !unsafe { GetLastInputInfo(&mut info) }.as_bool() {
+        return None;
+    }
+    let now = unsafe { GetTickCount() };
+    Some(Duration::from_millis(now.wrapping_sub(info.dwTime).into()))
+}
+
+/// Idle time after which the session locks: the "Machine inactivity limit"
+/// policy, or else the timeout of a screensaver that shows the logon screen.
+/// `None` if neither is set.
+fn lock_timeout() -> Option<Duration> {
+    let policy = registry_dword(
+        HKEY_LOCAL_MACHINE,
+        w!("SOFTWARE\\Microsoft\\Windows\\CurrentVersion\\Policies\\System"),
+        w!("InactivityTimeoutSecs"),
+    );
+    if let Some(seconds) = policy.filter(|seconds| *seconds > 0) {
+        return Some(Duration::from_secs(seconds.into()));
+    }
+    let screensaver_active = registry_string(
+        HKEY_CURRENT_USER,
+        w!("Control Panel\\Desktop"),
+        w!("ScreenSaveActive"),
+    );
+    if screensaver_active.as_deref() != Some("1") || requires_password() != Some(true) {
+        return None;
+    }
+    registry_string(
+        HKEY_CURRENT_USER,
+        w!("Control Panel\\Desktop"),
+        w!("ScreenSaveTimeOut"),
+    )?
+    .parse::<u64>()
+    .ok()
+    .filter(|seconds| *seconds > 0)
+    .map(Duration::from_secs)
+}
+
+/// Reads the "On resume, display logon screen" screensaver setting
+/// (`ScreenSaverIsSecure`). Group policy may enforce a different value.
+pub(crate) fn requires_password() -> Option<bool> {
+    let value = registry_string(
+        HKEY_CURRENT_USER,
+        w!("Control Panel\\Desktop"),
+        w!("ScreenSaverIsSecure"),
+    )?;
+    Some(value == "1")
+}
+
+fn registry_string(key: HKEY, subkey: PCWSTR, name: PCWSTR) -> Option<String> {
+    let mut value = [0u16; 16];
+    let mut size = std::mem::size_of_val(&value) as u32;
+    let result = unsafe {
+        RegGetValueW(
+            key,
+            subkey,
+            name,
+            RRF_RT_REG_SZ,
+            None,
+            Some(value.as_mut_ptr().cast()),
+            Some(&mut size),
+        )
+    };
+    if result.is_err() {
+        return None;
+    }
+    let len = value.iter().position(|c| *c == 0).unwrap_or(value.len());
+    Some(String::from_utf16_lossy(&value[..len]))
+}
+
+fn registry_dword(key: HKEY, subkey: PCWSTR, name: PCWSTR) -> Option<u32> {
+    let mut value = 0u32;
+    let mut size = std::mem::size_of_val(&value) as u32;
+    let result = unsafe {
+        RegGetValueW(
+            key,
+            subkey,
+            name,
+            RRF_RT_REG_DWORD,
+            None,
+            Some((&mut value as *mut u32).cast()),
+            Some(&mut size),
+        )
+    };
+    if result.is_err() {
+        return None;
+    }
+    Some(value)
+}
@@ -0,0 +1,62 @@
+//! `WM_WTSSESSION_CHANGE` codes, as defined in `WinUser.h`. They are kept
+//! apart from the `windows` crate so their mapping is tested on every
+//! platform.
+
+const WTS_REMOTE_CONNECT: u32 = 0x3;
+const WTS_REMOTE_DISCONNECT: u32 = 0x4;
+const WTS_SESSION_LOGON: u32 = 0x5;
+const WTS_SESSION_LOGOFF: u32 = 0x6;
+const WTS_SESSION_LOCK: u32 = 0x7;
+const WTS_SESSION_UNLOCK: u32 = 0x8;
+
+/// Maps a `WM_WTSSESSION_CHANGE` code to the lock state it reports:
+///
+/// | Code                    | Locked                               |
+/// |-------------------------|--------------------------------------|
+/// | `WTS_SESSION_LOCK`      | `true`                               |
+/// | `WTS_SESSION_UNLOCK`    | `false`                              |
+/// | `WTS_SESSION_LOGOFF`    | `true`                               |
+/// | `WTS_SESSION_LOGON`     | `false`                              |
+/// | `WTS_REMOTE_DISCONNECT` | `true` with `remote_session_events`  |
+/// | `WTS_REMOTE_CONNECT`    | `false` with `remote_session_events` |
+///
+/// Other codes, e.g. console connects, report nothing.
+pub(crate) fn session_locked(code: u32, remote_session_events: bool) -> Option<bool> {
+    match code {
+        WTS_SESSION_LOCK | WTS_SESSION_LOGOFF => Some(true),
+        WTS_SESSION_UNLOCK | WTS_SESSION_LOGON => Some(false),
+        WTS_REMOTE_DISCONNECT if remote_session_events => Some(true),
+        WTS_REMOTE_CONNECT if remote_session_events => Some(false),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WTS_CONSOLE_CONNECT: u32 = 0x1;
+    const WTS_CONSOLE_DISCONNECT: u32 = 0x2;
+
+    #[test]
+    fn session_codes() {
+        let cases = [
+            (WTS_SESSION_LOCK, Some(true), Some(true)),
+            (WTS_SESSION_UNLOCK, Some(false), Some(false)),
+            (WTS_SESSION_LOGON, Some(false), Some(false)),
+            (WTS_SESSION_LOGOFF, Some(true), Some(true)),
+            (WTS_REMOTE_CONNECT, None, Some(false)),
+            (WTS_REMOTE_DISCONNECT, None, Some(true)),
+            (WTS_CONSOLE_CONNECT, None, None),
+            (WTS_CONSOLE_DISCONNECT, None, None),
+        ];
+        for (code, without_remote, with_remote) in cases {
+            assert_eq!(
+                session_locked(code, false),
+                without_remote,
+                "code {code:#x}"
+            );
+            assert_eq!(session_locked(code, true), with_remote, "code {code:#x}");
+        }
+    }
+}